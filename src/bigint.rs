@@ -0,0 +1,365 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
+
+const BASE: f64 = 4294967296.0;
+
+/// An arbitrary-precision integer: a sign and a little-endian magnitude in
+/// base 2^32 without trailing zero digits. Zero is never negative, so the
+/// derived `PartialEq` compares values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct ParseBigIntError;
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> Self {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt { negative: negative && !digits.is_empty(), digits }
+    }
+
+    pub fn from_i64(i: i64) -> Self {
+        let m = i.unsigned_abs();
+        BigInt::new(i < 0, vec![m as u32, (m >> 32) as u32])
+    }
+
+    /// Only integral, finite floats can be converted.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() || f.fract() != 0.0 {
+            return None
+        }
+        let mut x = f.abs();
+        let mut digits = Vec::new();
+        while x > 0.0 {
+            let digit = x % BASE;
+            digits.push(digit as u32);
+            x = (x - digit) / BASE;
+        }
+        Some(BigInt::new(f < 0.0, digits))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None
+        }
+        let m = self.digits.iter().rev().fold(0u64, |acc, &d| (acc << 32) | d as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(m)
+        }
+        else {
+            i64::try_from(m).ok()
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let m = self.digits.iter().rev().fold(0.0, |acc, &d| acc * BASE + d as f64);
+        if self.negative { -m } else { m }
+    }
+
+    /// The number of bits in the magnitude.
+    pub fn bits(&self) -> u64 {
+        match self.digits.last() {
+            Some(d) => self.digits.len() as u64 * 32 - d.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn pow(&self, mut exp: u64) -> BigInt {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = &result * &base;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// Truncating division, like `i64`'s `/` and `%`.
+    ///
+    /// Panics if `other` is zero.
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        assert!(!other.is_zero(), "attempt to divide by zero");
+        let (q, r) = div_rem_magnitude(&self.digits, &other.digits);
+        (BigInt::new(self.negative != other.negative, q), BigInt::new(self.negative, r))
+    }
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    result.push(carry as u32);
+    result
+}
+
+// `a` must not be smaller than `b`.
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    result
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    result
+}
+
+fn div_rem_small(a: &[u32], b: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut r = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = (r << 32) | a[i] as u64;
+        q[i] = (cur / b as u64) as u32;
+        r = cur % b as u64;
+    }
+    (q, r as u32)
+}
+
+// Binary long division. It's quadratic, but simple and fine for the sizes
+// a script deals with.
+fn div_rem_magnitude(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (q, r) = div_rem_small(a, b[0]);
+        return (q, vec![r])
+    }
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+    for i in (0..a.len() * 32).rev() {
+        // r = r << 1 | bit i of a
+        let mut carry = (a[i / 32] >> (i % 32)) & 1;
+        for d in r.iter_mut() {
+            let next = *d >> 31;
+            *d = (*d << 1) | carry;
+            carry = next;
+        }
+        if carry != 0 {
+            r.push(carry);
+        }
+        if cmp_magnitude(&r, b) != Ordering::Less {
+            r = sub_magnitude(&r, b);
+            while r.last() == Some(&0) {
+                r.pop();
+            }
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+    (q, r)
+}
+
+impl<'a> Add<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_magnitude(&self.digits, &other.digits))
+        }
+        match cmp_magnitude(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, sub_magnitude(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_magnitude(&self.digits, &other.digits)),
+        }
+    }
+}
+
+impl<'a> Sub<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl<'a> Mul<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul_magnitude(&self.digits, &other.digits))
+    }
+}
+
+impl<'a> Div<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn div(self, other: &BigInt) -> BigInt {
+        self.div_rem(other).0
+    }
+}
+
+impl<'a> Rem<&'a BigInt> for &'a BigInt {
+    type Output = BigInt;
+
+    fn rem(self, other: &BigInt) -> BigInt {
+        self.div_rem(other).1
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_magnitude(&self.digits, &other.digits),
+            (true, true) => cmp_magnitude(&other.digits, &self.digits),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0")
+        }
+        // Peel off 9 decimal digits at a time
+        let mut chunks = Vec::new();
+        let mut m = self.digits.clone();
+        while !m.is_empty() {
+            let (q, r) = div_rem_small(&m, 1_000_000_000);
+            chunks.push(r);
+            m = q;
+            while m.last() == Some(&0) {
+                m.pop();
+            }
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks[chunks.len() - 1])?;
+        for chunk in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ParseBigIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if unsigned.is_empty() || !unsigned.chars().all(|x| x.is_ascii_digit()) {
+            return Err(ParseBigIntError)
+        }
+        let mut digits: Vec<u32> = Vec::new();
+        for c in unsigned.chars() {
+            // digits = digits * 10 + c
+            let mut carry = c.to_digit(10).unwrap() as u64;
+            for d in digits.iter_mut() {
+                let t = *d as u64 * 10 + carry;
+                *d = t as u32;
+                carry = t >> 32;
+            }
+            if carry != 0 {
+                digits.push(carry as u32);
+            }
+        }
+        Ok(BigInt::new(negative, digits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for s in &["0", "1", "-1", "4294967296", "-9223372036854775809",
+                   "123456789012345678901234567890", "1000000000000000000000000000"] {
+            assert_eq!(*s, big(s).to_string());
+        }
+        assert_eq!("0", big("-0").to_string());
+        assert_eq!("7", big("+7").to_string());
+        assert!("12a".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn conversion() {
+        assert_eq!(Some(i64::MAX), BigInt::from_i64(i64::MAX).to_i64());
+        assert_eq!(Some(i64::MIN), BigInt::from_i64(i64::MIN).to_i64());
+        assert_eq!(None, big("9223372036854775808").to_i64());
+        assert_eq!(None, big("-9223372036854775809").to_i64());
+        assert_eq!(1e30, big("1000000000000000000000000000000").to_f64());
+        assert_eq!(big("-1267650600228229401496703205376"), BigInt::from_f64(-2f64.powi(100)).unwrap());
+        assert!(BigInt::from_f64(0.5).is_none());
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(big("-864197532086419753208641975320"), &a + &b);
+        assert_eq!(big("1111111110111111111011111111100"), &a - &b);
+        assert_eq!(big("-121932631137021795226185032733622923332237463801111263526900"), &a * &b);
+        assert_eq!(big("-8"), &b / &a);
+        assert_eq!(big("-9000000000900000000090"), &b % &a);
+        assert_eq!(big("0"), &a - &a);
+        assert_eq!(big("1267650600228229401496703205376"), BigInt::from_i64(2).pow(100));
+        assert_eq!(big("-3"), &big("-7") / &big("2"));
+        assert_eq!(big("-1"), &big("-7") % &big("2"));
+        assert!(b < a);
+        assert!(big("-2") < big("-1"));
+    }
+}
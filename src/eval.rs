@@ -1,79 +1,470 @@
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::Path;
 use std::rc::Rc;
-use parser::Node;
+use lexer::{number_token, Lexer, Token};
+use number::Number;
+use parser::{Function, Node, Parser};
+use span::{SourceMap, Span};
+use symbol::Symbol;
+use truthiness::Truthiness;
+use LispError;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalErrorKind {
+    /// A function was called with the wrong number of arguments.
+    ArityMismatch { expected: usize, got: usize },
+    /// `()` was evaluated as a function call.
+    EmptyApplication,
+    /// A symbol that isn't bound was evaluated or called.
+    UnboundVariable { name: Symbol },
+    DivisionByZero,
+    /// The result is too large to be represented.
+    Overflow,
+    /// Evaluation nested deeper than `MAX_DEPTH`, like in a runaway
+    /// recursion that isn't in tail position.
+    TooDeep,
+    Other,
+}
+
+/// `span` points at the innermost form being evaluated when the error
+/// happened, if it came from parsed code. `trace` names the functions the
+/// error was raised in, innermost first. Functions without a name and calls
+/// in tail position, which don't return to their caller, are left out.
 #[derive(Debug, Clone)]
-pub struct EvalError(String);
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    pub trace: Vec<Symbol>,
+}
 
-pub struct Eval;
+impl EvalError {
+    pub fn new(kind: EvalErrorKind, message: String) -> Self {
+        EvalError { kind, message, span: None, trace: Vec::new() }
+    }
 
-#[derive(Debug, Clone)]
+    pub fn other(message: String) -> Self {
+        EvalError::new(EvalErrorKind::Other, message)
+    }
+
+    // Records that the error is leaving the function `name`, if it has one.
+    fn traced(mut self, name: Option<Symbol>) -> Self {
+        self.trace.extend(name);
+        self
+    }
+}
+
+/// The message followed by a line for each function in the trace. Runs of
+/// the same function, like from recursion, are shown once.
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        let mut i = 0;
+        while i < self.trace.len() {
+            let name = self.trace[i];
+            let count = self.trace[i..].iter().take_while(|x| **x == name).count();
+            write!(f, "\n    in `{}`", name)?;
+            if count > 1 {
+                write!(f, " ({} times)", count)?;
+            }
+            i += count;
+        }
+        Ok(())
+    }
+}
+
+/// How deep `Eval::eval` can be nested, including calls that aren't in
+/// tail position. Each level takes a few frames of the Rust stack, so going
+/// much deeper would overflow it and abort the process.
+pub const MAX_DEPTH: usize = 500;
+
+pub struct Eval {
+    source_map: RefCell<SourceMap>,
+    truthiness: Truthiness,
+    depth: Cell<usize>,
+}
+
+/// What's left to do after evaluating one step of a form. Forms in tail
+/// position hand back `Eval` instead of recursing, so that `Eval::eval` can
+/// run them in a loop without growing the Rust stack.
+enum Tail {
+    Return(Rc<Node>),
+    Eval(Env, Rc<Node>),
+    /// Like `Eval`, for the last form of the body of the function `name`
+    /// being called.
+    Call(Option<Symbol>, Env, Rc<Node>),
+}
+
+/// A chain of variable frames. Cloning an `Env` is cheap and shares the
+/// frames, which is what lets a `Node::Func` capture the environment it was
+/// created in.
+#[derive(Clone)]
 pub struct Env {
-    envs: Vec<HashMap<String, Rc<Node>>>
+    vars: Rc<RefCell<HashMap<Symbol, Rc<Node>>>>,
+    parent: Option<Rc<Env>>,
+    // Set for frames that the functions bound in them refer back to.
+    recursive: bool,
 }
 
 impl Env {
     pub fn new() -> Self {
         Env {
-            envs: vec![HashMap::new()]
+            vars: Rc::new(RefCell::new(HashMap::new())),
+            parent: None,
+            recursive: false,
         }
     }
 
-    pub fn new_with_map(map: HashMap<String, Node>) -> Self {
+    pub fn new_with_map(map: HashMap<Symbol, Node>) -> Self {
         Env {
-            envs: vec![map.iter().map(|(k, v)| (k.clone(), Rc::new(v.clone()))).collect::<HashMap<String, Rc<Node>>>()]
+            vars: Rc::new(RefCell::new(
+                    map.iter().map(|(k, v)| (*k, Rc::new(v.clone()))).collect::<HashMap<Symbol, Rc<Node>>>())),
+            parent: None,
+            recursive: false,
+        }
+    }
+
+    pub fn get(&self, key: Symbol) -> Option<Rc<Node>> {
+        if let Some(v) = self.vars.borrow().get(&key) {
+            return Some(v.clone())
+        }
+        match self.parent {
+            Some(ref parent) => parent.get(key),
+            None => None,
         }
     }
 
-    pub fn get(&self, key: &String) -> Option<Rc<Node>> {
-        // for env in &self.envs {
-        for i in 0..self.envs.len() {
-            let env = &self.envs[self.envs.len() - 1 - i];
-            if let Some(v) = env.get(key) {
-                return Some(v.clone())
+    pub fn insert(&mut self, k: Symbol, v: Rc<Node>) -> Option<Rc<Node>> {
+        self.vars.borrow_mut().insert(k, v)
+    }
+
+    /// Assigns to the innermost existing binding of `k`, or creates one in
+    /// the current frame if `k` isn't bound anywhere.
+    pub fn set(&mut self, k: Symbol, v: Rc<Node>) -> Option<Rc<Node>> {
+        let mut env = &*self;
+        loop {
+            if env.vars.borrow().contains_key(&k) {
+                return env.vars.borrow_mut().insert(k, v)
+            }
+            match env.parent {
+                Some(ref parent) => env = parent,
+                None => break,
             }
         }
-        None
+        self.insert(k, v)
+    }
+
+    pub fn remove(&mut self, k: Symbol) -> Option<Rc<Node>> {
+        self.vars.borrow_mut().remove(&k)
+    }
+
+    /// Returns a new environment with an empty frame on top of this one.
+    /// `self` is left as it is, so there's nothing to undo when evaluation
+    /// in the new frame fails halfway.
+    pub fn extend(&self) -> Env {
+        Env {
+            vars: Rc::new(RefCell::new(HashMap::new())),
+            parent: Some(Rc::new(self.clone())),
+            recursive: false,
+        }
+    }
+
+    /// Like `extend`, for a frame that the functions bound in it will refer
+    /// back to, like the one `letrec` makes. Once nothing but those
+    /// functions refers to it, the frame is emptied so that it and the
+    /// functions can be freed.
+    pub fn extend_recursive(&self) -> Env {
+        Env {
+            vars: Rc::new(RefCell::new(HashMap::new())),
+            parent: Some(Rc::new(self.clone())),
+            recursive: true,
+        }
+    }
+
+    // Whether every other reference to this frame is from a function bound
+    // in it that nothing else refers to, which makes the frame unreachable
+    // once this one is gone.
+    fn is_only_cycle(&self) -> bool {
+        let vars = match self.vars.try_borrow() {
+            Ok(vars) => vars,
+            Err(_) => return false,
+        };
+        let cycles = vars.values().filter(|x| match ***x {
+            Node::Func(ref f) => Rc::strong_count(x) == 1 && Rc::strong_count(f) == 1 && Rc::ptr_eq(&f.env.vars, &self.vars),
+            _ => false,
+        }).count();
+        Rc::strong_count(&self.vars) == cycles + 1
+    }
+
+    /// Every name bound in this environment or the ones it extends.
+    pub fn names(&self) -> Vec<Symbol> {
+        let mut names: Vec<Symbol> = self.vars.borrow().keys().cloned().collect();
+        if let Some(ref parent) = self.parent {
+            names.extend(parent.names());
+        }
+        names
     }
 
-    pub fn insert(&mut self, k: String, v: Rc<Node>) -> Option<Rc<Node>> {
-        if self.envs.is_empty() {
-            panic!("Env#insert shouldn't be called for empty `envs`");
+    fn depth(&self) -> usize {
+        match self.parent {
+            Some(ref parent) => parent.depth() + 1,
+            None => 1,
         }
-        let len = self.envs.len();
-        let env = &mut self.envs[len - 1];
-        env.insert(k, v)
     }
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
+}
 
-    pub fn remove(&mut self, k: &String) -> Option<Rc<Node>> {
-        if self.envs.is_empty() {
-            panic!("Env#remove shouldn't be called for empty `envs`");
+// A function bound in a recursive frame holds the frame, which holds the
+// function, so counting references alone would never free either of them.
+impl Drop for Env {
+    fn drop(&mut self) {
+        if self.recursive && self.is_only_cycle() {
+            // Dropped after the borrow ends, since the functions drop their
+            // own handles on the frame
+            let vars = mem::take(&mut *self.vars.borrow_mut());
+            drop(vars);
         }
-        let len = self.envs.len();
-        let env = &mut self.envs[len - 1];
-        env.remove(k)
     }
+}
+
+// Frames can (indirectly) contain themselves through captured closures, so
+// neither the derived `Debug` nor `PartialEq` would terminate.
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Env {{ depth: {} }}", self.depth())
+    }
+}
 
-    pub fn push_env(&mut self) {
-        self.envs.push(HashMap::new());
+impl PartialEq for Env {
+    fn eq(&self, other: &Env) -> bool {
+        Rc::ptr_eq(&self.vars, &other.vars)
     }
+}
 
-    pub fn pop_env(&mut self) {
-        self.envs.pop();
+// The `k`th element of `list`, or `None` if the list runs out first.
+fn nth(list: &Rc<Node>, k: usize) -> Option<Rc<Node>> {
+    let mut list = list;
+    for _ in 0..k {
+        match **list {
+            Node::Pair(_, ref tl) => list = tl,
+            _ => return None,
+        }
+    }
+    match **list {
+        Node::Pair(ref hd, _) => Some(hd.clone()),
+        _ => None,
     }
 }
 
+// The number of chars to insert, delete, replace or swap with the next one
+// to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // d[i][j] is the distance between the first i chars of `a` and the
+    // first j chars of `b`
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replace = d[i - 1][j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = replace.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// The name in `names` closest to `name`, if it's close enough to be what a
+// typo was meant to be. Names that share nothing with `name` never are.
+fn suggest(name: Symbol, names: &[Symbol]) -> Option<Symbol> {
+    let len = name.name().chars().count();
+    let limit = (len / 3).max(1);
+    names.iter()
+        .filter(|x| **x != name)
+        .map(|x| (edit_distance(name.name(), x.name()), x.name(), *x))
+        .filter(|x| x.0 <= limit && x.0 < len)
+        .min_by_key(|x| (x.0, x.1))
+        .map(|x| x.2)
+}
+
+// The special forms and builtin functions `Eval::eval_func` knows.
+const BUILTINS: &[&str] = &[
+    "if", "when", "unless", "cond", "case", "and", "or", "quote", "setq", "progn", "begin",
+    "lambda", "defun", "define", "let", "let*", "letrec",
+    "+", "-", "*", "/", "=", ">", ">=", "<", "<=", "/=",
+    "floor", "ceiling", "round", "truncate", "sqrt", "exp", "log", "sin", "cos", "tan",
+    "exact->inexact", "inexact->exact", "expt", "not",
+    "car", "cdr", "cons", "list", "null?", "pair?", "length", "append", "reverse", "list-ref", "nth", "last",
+    "string-length", "substring", "string-append", "string=", "string<", "string->number", "number->string",
+    "write", "display", "load", "documentation",
+];
+
+// `message` about `name` not being bound in `env`, with a suggestion from
+// the names bound in it and the builtins.
+fn unbound(env: &Env, name: Symbol, message: &str) -> EvalError {
+    let mut message = format!("{}: {:?}", message, name);
+    let mut names = env.names();
+    names.extend(BUILTINS.iter().map(|x| Symbol::intern(x)));
+    if let Some(x) = suggest(name, &names) {
+        message.push_str(&format!(", did you mean {:?}?", x));
+    }
+    EvalError::new(EvalErrorKind::UnboundVariable { name }, message)
+}
+
+// A function of `params` and `body`. A string at the start of `body` is
+// the documentation, unless it's the only form.
+fn function(env: &Env, name: Option<Symbol>, params: &Rc<Node>, body: &[Rc<Node>]) -> Option<Rc<Node>> {
+    let mut symbols = Vec::new();
+    for x in params.to_vec()? {
+        match *x {
+            Node::Symbol(name) => symbols.push(name),
+            _ => return None,
+        }
+    }
+    let (doc, body) = match body.split_first() {
+        Some((first, rest)) if !rest.is_empty() => match **first {
+            Node::Str(ref doc) => (Some(doc.clone()), rest),
+            _ => (None, body),
+        },
+        Some(_) => (None, body),
+        None => return None,
+    };
+    Some(Rc::new(Node::Func(Rc::new(Function {
+        name,
+        params: symbols,
+        doc,
+        body: body.to_vec(),
+        env: env.clone(),
+    }))))
+}
+
+// Reads the `((name value) ...)` of a `let`-like form.
+fn bindings(node: &Rc<Node>) -> Option<Vec<(Symbol, Rc<Node>)>> {
+    let mut result = Vec::new();
+    for x in node.to_vec()? {
+        match x.to_vec().as_deref() {
+            Some([name, value]) => match **name {
+                Node::Symbol(name) => result.push((name, value.clone())),
+                _ => return None,
+            },
+            _ => return None,
+        }
+    }
+    Some(result)
+}
+
+// The catch-all test of `cond` and `case` clauses.
+fn is_else(node: &Rc<Node>) -> bool {
+    match **node {
+        Node::Symbol(name) => name.name() == "else" || name.name() == "otherwise",
+        _ => false,
+    }
+}
+
+// `args` printed one after another, for error messages.
+fn show_args(args: &[Rc<Node>]) -> String {
+    if args.is_empty() {
+        return String::from("nothing")
+    }
+    args.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn boolean(b: bool) -> Rc<Node> {
+    Rc::new(if b { Node::True } else { Node::False })
+}
+
+impl Default for Eval {
+    fn default() -> Self {
+        Eval::new()
+    }
+}
+
+// The name a form is called by, for error messages.
+fn operator(node: &Node) -> String {
+    if let Node::Pair(ref hd, _) = *node {
+        if let Node::Symbol(name) = **hd {
+            return format!("`{}`", name)
+        }
+    }
+    String::from("The function")
+}
+
 // TODO: Reduce memory copy...
 impl Eval {
     pub fn new() -> Self {
-        Eval {}
+        Eval::with_truthiness(Truthiness::default())
+    }
+
+    pub fn with_truthiness(truthiness: Truthiness) -> Self {
+        Eval { source_map: RefCell::new(SourceMap::new()), truthiness, depth: Cell::new(0) }
     }
 
-    fn calc_integer<F>(&self,
-                       env: &mut Env,
-                       f: &F,
-                       args: &[Rc<Node>], node: &Rc<Node>) -> Result<Rc<Node>, EvalError>
-        where F: Fn(i64, i64) -> i64 {
+    pub fn truthiness(&self) -> Truthiness {
+        self.truthiness
+    }
+
+    pub fn is_true(&self, node: &Node) -> bool {
+        match *node {
+            Node::False => false,
+            Node::Nil => self.truthiness == Truthiness::Scheme,
+            _ => true,
+        }
+    }
+
+    /// Keeps `source` around so that errors pointing into it can be rendered.
+    pub fn add_source(&self, file: Rc<String>, source: &str) {
+        self.source_map.borrow_mut().add_source(file, source)
+    }
+
+    /// Like `add_source`, for a source read from the path `file`. Relative
+    /// paths given to `load` in it are resolved against its directory.
+    pub fn add_file(&self, file: Rc<String>, source: &str) {
+        self.source_map.borrow_mut().add_file(file, source)
+    }
+
+    pub fn source(&self, file: &Rc<String>) -> Option<Rc<String>> {
+        self.source_map.borrow().source(file)
+    }
+
+    /// Registers where parsed nodes came from, so that errors raised while
+    /// evaluating them get a span.
+    pub fn add_spans(&self, spans: Vec<(Rc<Node>, Span)>) {
+        self.source_map.borrow_mut().add_spans(spans)
+    }
+
+    // The file `node` was read from, if it was read from one.
+    fn file_of(&self, node: &Rc<Node>) -> Option<Rc<String>> {
+        let source_map = self.source_map.borrow();
+        source_map.get(node).and_then(|x| x.file).filter(|x| source_map.is_file(x))
+    }
+
+    // Points `err` at `node` unless it already points somewhere more precise.
+    fn locate(&self, mut err: EvalError, node: &Rc<Node>) -> EvalError {
+        if err.span.is_none() {
+            err.span = self.source_map.borrow().get(node);
+        }
+        err
+    }
+
+    fn calc_number<F>(&self,
+                      env: &mut Env,
+                      f: &F,
+                      args: &[Rc<Node>], node: &Rc<Node>) -> Result<Rc<Node>, EvalError>
+        where F: Fn(Number, Number) -> Result<Number, EvalError> {
 
         let result =
             args.iter().fold(
@@ -81,14 +472,14 @@ impl Eval {
                 |a, x| {
                     let nd = self.eval(env, x.clone());
                     match nd {
-                        Ok(rcnode) => match *rcnode.clone() {
-                            Node::Integer(i) => match a {
-                                Some(Ok(aa)) => Some(Ok(f(aa, i))),
+                        Ok(rcnode) => match Number::from_node(&rcnode) {
+                            Some(n) => match a {
+                                Some(Ok(aa)) => Some(f(aa, n)),
                                 Some(Err(err)) => Some(Err(err)),
-                                None => Some(Ok(i)),
+                                None => Some(Ok(n)),
                             },
-                            _ => Some(Err(EvalError(
-                                        format!("{:?} takes only an integer, but got {:?}", node, x)))),
+                            None => Some(Err(self.locate(EvalError::other(
+                                        format!("{} takes only numbers, but got {}", operator(node), rcnode)), x))),
                         },
                         Err(err) => Some(Err(err)),
                     }
@@ -96,9 +487,9 @@ impl Eval {
             );
 
         match result {
-            Some(Ok(i)) => Ok(Rc::new(Node::Integer(i))),
+            Some(Ok(n)) => Ok(Rc::new(n.into_node())),
             Some(Err(err)) => Err(err),
-            None => Err(EvalError(format!("Empty argument")))
+            None => Err(EvalError::other(String::from("Empty argument")))
         }
     }
 
@@ -107,7 +498,7 @@ impl Eval {
                f: &F,
                args: &[Rc<Node>],
                node: &Rc<Node>) -> Result<Rc<Node>, EvalError>
-        where F: Fn(i64, i64) -> bool {
+        where F: Fn(&Number, &Number) -> bool {
 
         let result =
             args.iter().fold(
@@ -115,14 +506,14 @@ impl Eval {
                 |a, x| {
                     let nd = self.eval(env, x.clone());
                     match nd {
-                        Ok(rcnode) => match *rcnode.clone() {
-                            Node::Integer(i) => match a {
-                                Some(Ok((result, prev))) => Some(Ok((result && f(prev, i), i))),
+                        Ok(rcnode) => match Number::from_node(&rcnode) {
+                            Some(n) => match a {
+                                Some(Ok((result, prev))) => Some(Ok((result && f(&prev, &n), n))),
                                 Some(Err(err)) => Some(Err(err)),
-                                None => Some(Ok((true, i))),
+                                None => Some(Ok((true, n))),
                             },
-                            _ => Some(Err(EvalError(
-                                        format!("{:?} takes only an integer, but got {:?}", node, x)))),
+                            None => Some(Err(self.locate(EvalError::other(
+                                        format!("{} takes only numbers, but got {}", operator(node), rcnode)), x))),
                         },
                         Err(err) => Some(Err(err)),
                     }
@@ -133,69 +524,608 @@ impl Eval {
             Some(Ok(result)) => Ok(if result.0 { Rc::new(Node::True) }
                                    else { Rc::new(Node::False) }),
             Some(Err(err)) => Err(err),
-            None => Err(EvalError(format!("Empty argument")))
+            None => Err(EvalError::other(String::from("Empty argument")))
         }
     }
 
+    fn math<F>(&self,
+               env: &mut Env,
+               f: &F,
+               args: &[Rc<Node>],
+               node: &Rc<Node>) -> Result<Rc<Node>, EvalError>
+        where F: Fn(Number) -> Result<Number, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Some(n) = Number::from_node(&args[0]) {
+                return Ok(Rc::new(f(n)?.into_node()))
+            }
+        }
+
+        Err(EvalError::other(format!("{} takes only a number, but got {}", operator(node), show_args(&args))))
+    }
+
+    fn expt(&self,
+            env: &mut Env,
+            args: &[Rc<Node>],
+            _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 2 {
+            if let (Some(a), Some(b)) = (Number::from_node(&args[0]), Number::from_node(&args[1])) {
+                return match a.checked_expt(b) {
+                    Some(n) => Ok(Rc::new(n.into_node())),
+                    None => Err(EvalError::new(
+                            EvalErrorKind::Overflow, format!("`expt` result is too large: {}", show_args(&args)))),
+                }
+            }
+        }
+
+        Err(EvalError::other(format!("`expt` takes (base:number exponent:number), but got {}", show_args(&args))))
+    }
+
+    // Evaluates the condition `form`.
+    fn test(&self, env: &mut Env, form: &Rc<Node>) -> Result<bool, EvalError> {
+        let value = self.eval(env, form.clone())?;
+        Ok(self.is_true(&value))
+    }
+
     fn if_then_else(&self,
                     env: &mut Env,
                     args: &[Rc<Node>],
-                    node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+                    _node: &Rc<Node>) -> Result<Tail, EvalError> {
 
         if args.len() < 2 || args.len() > 3 {
-            return Err(EvalError(
-                    format!("`if` takes 2 or 3 arguments, but got {:?}", args)));
+            return Err(EvalError::other(
+                    format!("`if` takes 2 or 3 arguments, but got {}", show_args(args))));
         }
 
-        Ok(match *try!(self.eval(env, args[0].clone())).clone() {
-            Node::True => try!(self.eval(env, args[1].clone())),
-            Node::False => {
-                if args.len() == 3 {
-                    try!(self.eval(env, args[2].clone()))
+        Ok(if self.test(env, &args[0])? {
+            Tail::Eval(env.clone(), args[1].clone())
+        }
+        else if args.len() == 3 {
+            Tail::Eval(env.clone(), args[2].clone())
+        }
+        else {
+            Tail::Return(Rc::new(Node::Nil))
+        })
+    }
+
+    // `(when test body...)` runs the body if `test` holds, and `unless`
+    // if it doesn't. Otherwise they give `()`.
+    fn when(&self,
+            env: &mut Env,
+            args: &[Rc<Node>],
+            node: &Rc<Node>,
+            expected: bool) -> Result<Tail, EvalError> {
+
+        match args.split_first() {
+            Some((test, body)) => if self.test(env, test)? == expected {
+                self.body(env, body)
+            }
+            else {
+                Ok(Tail::Return(Rc::new(Node::Nil)))
+            },
+            None => Err(EvalError::other(format!("{} takes only (test body...), but got {}", operator(node), show_args(args)))),
+        }
+    }
+
+    // Stops at the first operand that's false and gives it, or gives the
+    // last one, which is in tail position. `(and)` is `#t`.
+    fn and(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        match args.split_last() {
+            Some((last, init)) => {
+                for x in init {
+                    let value = self.eval(env, x.clone())?;
+                    if !self.is_true(&value) {
+                        return Ok(Tail::Return(value))
+                    }
                 }
-                else {
-                    Rc::new(Node::List(Vec::new()))
+                Ok(Tail::Eval(env.clone(), last.clone()))
+            },
+            None => Ok(Tail::Return(boolean(true))),
+        }
+    }
+
+    // Stops at the first operand that's true and gives it, or gives the
+    // last one, which is in tail position. `(or)` is `#f`.
+    fn or(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        match args.split_last() {
+            Some((last, init)) => {
+                for x in init {
+                    let value = self.eval(env, x.clone())?;
+                    if self.is_true(&value) {
+                        return Ok(Tail::Return(value))
+                    }
                 }
+                Ok(Tail::Eval(env.clone(), last.clone()))
             },
-            _ => return Err(EvalError(
-                    format!("The 1st parameter of `if` should be boolean, but got {:?}", args)))
-        })
+            None => Ok(Tail::Return(boolean(false))),
+        }
+    }
+
+    fn not(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+        if args.len() == 1 {
+            return Ok(boolean(!self.test(env, &args[0])?))
+        }
+
+        Err(EvalError::other(format!("`not` takes only one argument, but got {}", show_args(args))))
+    }
+
+    // `(cond (test body...) ... (else body...))` runs the body of the first
+    // clause whose test holds. A clause without a body gives its test's
+    // value, and `()` is given if no clause applies.
+    fn cond_form(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        for clause in args {
+            let xs = clause.to_vec().unwrap_or_default();
+            let (test, body) = match xs.split_first() {
+                Some(x) => x,
+                None => return Err(self.locate(EvalError::other(
+                            format!("A clause of `cond` should be (test body...), but got {}", clause)), clause)),
+            };
+            let value = if is_else(test) { boolean(true) } else { self.eval(env, test.clone())? };
+            if self.is_true(&value) {
+                return if body.is_empty() {
+                    Ok(Tail::Return(value))
+                }
+                else {
+                    self.body(env, body)
+                }
+            }
+        }
+        Ok(Tail::Return(Rc::new(Node::Nil)))
+    }
+
+    // `(case key ((datum...) body...) ... (else body...))` runs the body of
+    // the first clause that lists a datum equal to the value of `key`.
+    fn case(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        let (key, clauses) = match args.split_first() {
+            Some(x) => x,
+            None => return Err(EvalError::other(String::from("`case` takes only (key clauses...), but got nothing"))),
+        };
+        let key = self.eval(env, key.clone())?;
+        for clause in clauses {
+            let xs = clause.to_vec().unwrap_or_default();
+            if xs.len() < 2 {
+                return Err(self.locate(EvalError::other(
+                            format!("A clause of `case` should be ((datum...) body...), but got {}", clause)), clause))
+            }
+            let (data, body) = (&xs[0], &xs[1..]);
+            let matches = if is_else(data) {
+                true
+            }
+            else {
+                match data.to_vec() {
+                    Some(data) => data.iter().any(|x| **x == *key),
+                    None => return Err(self.locate(EvalError::other(
+                                format!("The data of a `case` clause should be a list, but got {}", data)), data)),
+                }
+            };
+            if matches {
+                return self.body(env, body)
+            }
+        }
+        Ok(Tail::Return(Rc::new(Node::Nil)))
+    }
+
+    fn quote(&self,
+             _env: &mut Env,
+             args: &[Rc<Node>],
+             _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        // Code is made of the same pairs as data, so there's nothing to convert
+        if args.len() == 1 {
+            return Ok(args[0].clone())
+        }
+
+        Err(EvalError::other(format!("`quote` takes only one argument, but got {}", show_args(args))))
+    }
+
+    // `list` as it is, or `()` if it's `#f` in Common Lisp, where both are
+    // `nil`.
+    fn as_list(&self, list: &Rc<Node>) -> Rc<Node> {
+        match **list {
+            Node::False if self.truthiness == Truthiness::CommonLisp => Rc::new(Node::Nil),
+            _ => list.clone(),
+        }
     }
 
     fn car(&self,
            env: &mut Env,
            args: &[Rc<Node>],
-           node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+           _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
 
+        let args = self.eval_args(env, args)?;
         if args.len() == 1 {
-            if let Node::QuotedList(ref xs) = *args[0].clone() {
-                return Ok(match xs.split_first() {
-                    Some((hd, _)) => hd.clone(),
-                    None => Rc::new(Node::List(Vec::new()))
-                })
+            let list = self.as_list(&args[0]);
+            match *list {
+                Node::Pair(ref hd, _) => return Ok(hd.clone()),
+                Node::Nil => return Ok(list.clone()),
+                _ => (),
             }
         }
 
-        Err(EvalError(format!("`car` takes only a quoted list, but got {:?}", args)))
+        Err(EvalError::other(format!("`car` takes only a list, but got {}", show_args(&args))))
     }
 
     fn cdr(&self,
            env: &mut Env,
            args: &[Rc<Node>],
-           node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+           _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            let list = self.as_list(&args[0]);
+            match *list {
+                Node::Pair(_, ref tl) => return Ok(tl.clone()),
+                Node::Nil => return Ok(list.clone()),
+                _ => (),
+            }
+        }
+
+        Err(EvalError::other(format!("`cdr` takes only a list, but got {}", show_args(&args))))
+    }
+
+    fn cons(&self,
+            env: &mut Env,
+            args: &[Rc<Node>],
+            _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 2 {
+            return Ok(Rc::new(Node::Pair(args[0].clone(), args[1].clone())))
+        }
+
+        Err(EvalError::other(format!("`cons` takes (car cdr), but got {}", show_args(&args))))
+    }
+
+    fn list(&self,
+            env: &mut Env,
+            args: &[Rc<Node>],
+            _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        Ok(Node::list(self.eval_args(env, args)?))
+    }
+
+    fn is_null(&self,
+               env: &mut Env,
+               args: &[Rc<Node>],
+               _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            return Ok(boolean(*self.as_list(&args[0]) == Node::Nil))
+        }
+
+        Err(EvalError::other(format!("`null?` takes only one argument, but got {}", show_args(&args))))
+    }
+
+    fn is_pair(&self,
+               env: &mut Env,
+               args: &[Rc<Node>],
+               _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Node::Pair(..) = *args[0] {
+                return Ok(boolean(true))
+            }
+            return Ok(boolean(false))
+        }
+
+        Err(EvalError::other(format!("`pair?` takes only one argument, but got {}", show_args(&args))))
+    }
+
+    fn length(&self,
+              env: &mut Env,
+              args: &[Rc<Node>],
+              _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Some(xs) = self.as_list(&args[0]).to_vec() {
+                return Ok(Rc::new(Node::Integer(xs.len() as i64)))
+            }
+        }
+
+        Err(EvalError::other(format!("`length` takes only a proper list, but got {}", show_args(&args))))
+    }
+
+    // Every list but the last is copied, and the last one becomes the tail
+    // of the result as it is.
+    fn append(&self,
+              env: &mut Env,
+              args: &[Rc<Node>],
+              _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let mut args = self.eval_args(env, args)?;
+        let tail = match args.pop() {
+            Some(x) => self.as_list(&x),
+            None => return Ok(Rc::new(Node::Nil)),
+        };
+        let mut items = Vec::new();
+        for x in &args {
+            match self.as_list(x).to_vec() {
+                Some(xs) => items.extend(xs),
+                None => return Err(EvalError::other(
+                        format!("`append` takes only proper lists but the last, but got {}", x))),
+            }
+        }
+        Ok(Node::dotted_list(items, tail))
+    }
+
+    fn reverse(&self,
+               env: &mut Env,
+               args: &[Rc<Node>],
+               _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Some(mut xs) = self.as_list(&args[0]).to_vec() {
+                xs.reverse();
+                return Ok(Node::list(xs))
+            }
+        }
+
+        Err(EvalError::other(format!("`reverse` takes only a proper list, but got {}", show_args(&args))))
+    }
+
+    fn list_ref(&self,
+                env: &mut Env,
+                args: &[Rc<Node>],
+                _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 2 {
+            let list = self.as_list(&args[0]);
+            if let (Node::Pair(..), &Node::Integer(k)) | (Node::Nil, &Node::Integer(k)) = (&*list, &*args[1]) {
+                if k >= 0 {
+                    return nth(&list, k as usize).ok_or_else(|| EvalError::other(
+                            format!("`list-ref` index {} is out of bounds for {}", k, args[0])))
+                }
+            }
+        }
+
+        Err(EvalError::other(format!("`list-ref` takes (list index), but got {}", show_args(&args))))
+    }
+
+    // Like `list-ref` with the arguments swapped, but gives `()` past the
+    // end of the list as Common Lisp does.
+    fn nth(&self,
+           env: &mut Env,
+           args: &[Rc<Node>],
+           _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 2 {
+            let list = self.as_list(&args[1]);
+            if let (&Node::Integer(k), Node::Pair(..)) | (&Node::Integer(k), Node::Nil) = (&*args[0], &*list) {
+                if k >= 0 {
+                    return Ok(nth(&list, k as usize).unwrap_or_else(|| Rc::new(Node::Nil)))
+                }
+            }
+        }
 
+        Err(EvalError::other(format!("`nth` takes (index list), but got {}", show_args(&args))))
+    }
+
+    // The last pair of a list, like in Common Lisp.
+    fn last(&self,
+            env: &mut Env,
+            args: &[Rc<Node>],
+            _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
         if args.len() == 1 {
-            if let Node::QuotedList(ref xs) = *args[0].clone() {
-                return Ok(
-                    match xs.split_first() {
-                        Some((_, tl)) => Rc::new(Node::QuotedList((*tl.clone()).to_vec())),
-                        None => Rc::new(Node::List(Vec::new()))
+            let list = self.as_list(&args[0]);
+            if let Node::Pair(..) | Node::Nil = *list {
+                let mut list = &list;
+                while let Node::Pair(_, ref tl) = **list {
+                    if let Node::Pair(..) = **tl {
+                        list = tl;
                     }
-                )
+                    else {
+                        break
+                    }
+                }
+                return Ok(list.clone())
+            }
+        }
+
+        Err(EvalError::other(format!("`last` takes only a list, but got {}", show_args(&args))))
+    }
+
+    fn string_length(&self,
+                     env: &mut Env,
+                     args: &[Rc<Node>],
+                     _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Node::Str(ref s) = *args[0] {
+                return Ok(Rc::new(Node::Integer(s.chars().count() as i64)))
             }
         }
 
-        Err(EvalError(format!("`cdr` takes only a quoted list, but got {:?}", args)))
+        Err(EvalError::other(format!("`string-length` takes only a string, but got {}", show_args(&args))))
+    }
+
+    fn substring(&self,
+                 env: &mut Env,
+                 args: &[Rc<Node>],
+                 _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 2 || args.len() == 3 {
+            if let Node::Str(ref s) = *args[0] {
+                let len = s.chars().count() as i64;
+                let start = match *args[1] {
+                    Node::Integer(i) => i,
+                    _ => return Err(EvalError::other(format!("`substring` takes an integer as start, but got {}", args[1]))),
+                };
+                let end = match args.get(2).map(|x| &**x) {
+                    Some(&Node::Integer(i)) => i,
+                    None => len,
+                    Some(x) => return Err(EvalError::other(format!("`substring` takes an integer as end, but got {}", x))),
+                };
+                if start < 0 || start > end || end > len {
+                    return Err(EvalError::other(format!(
+                                "`substring` range {}..{} is out of bounds for {}", start, end, args[0])))
+                }
+                return Ok(Rc::new(Node::Str(
+                            s.chars().skip(start as usize).take((end - start) as usize).collect())))
+            }
+        }
+
+        Err(EvalError::other(format!("`substring` takes (string start [end]), but got {}", show_args(&args))))
+    }
+
+    fn string_append(&self,
+                     env: &mut Env,
+                     args: &[Rc<Node>],
+                     node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let mut result = String::new();
+        for x in self.eval_args(env, args)? {
+            match *x {
+                Node::Str(ref s) => result.push_str(s),
+                _ => return Err(EvalError::other(format!("{} takes only strings, but got {}", operator(node), x))),
+            }
+        }
+        Ok(Rc::new(Node::Str(result)))
+    }
+
+    fn string_cond<F>(&self,
+                      env: &mut Env,
+                      f: &F,
+                      args: &[Rc<Node>],
+                      node: &Rc<Node>) -> Result<Rc<Node>, EvalError>
+        where F: Fn(&str, &str) -> bool {
+
+        let args = self.eval_args(env, args)?;
+        let mut strs = Vec::new();
+        for x in &args {
+            match **x {
+                Node::Str(ref s) => strs.push(s.as_str()),
+                _ => return Err(EvalError::other(format!("{} takes only strings, but got {}", operator(node), x))),
+            }
+        }
+
+        if strs.is_empty() {
+            return Err(EvalError::other(String::from("Empty argument")))
+        }
+        Ok(if strs.windows(2).all(|w| f(w[0], w[1])) { Rc::new(Node::True) }
+           else { Rc::new(Node::False) })
+    }
+
+    fn string_to_number(&self,
+                        env: &mut Env,
+                        args: &[Rc<Node>],
+                        _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Node::Str(ref s) = *args[0] {
+                // Anything that isn't exactly one number literal gives `False`.
+                return Ok(Rc::new(match number_token(s) {
+                    Some(Token::Integer(i)) => Node::Integer(i),
+                    Some(Token::BigInt(b)) => Node::BigInt(b),
+                    Some(Token::Float(f)) => Node::Float(f),
+                    _ => Node::False,
+                }))
+            }
+        }
+
+        Err(EvalError::other(format!("`string->number` takes only a string, but got {}", show_args(&args))))
+    }
+
+    fn number_to_string(&self,
+                        env: &mut Env,
+                        args: &[Rc<Node>],
+                        _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Some(n) = Number::from_node(&args[0]) {
+                return Ok(Rc::new(Node::Str(n.to_string())))
+            }
+        }
+
+        Err(EvalError::other(format!("`number->string` takes only a number, but got {}", show_args(&args))))
+    }
+
+    // `write` prints its argument so that it reads back as the same datum,
+    // and `display` prints strings as they are. Either gives the argument
+    // back.
+    fn print(&self,
+             env: &mut Env,
+             args: &[Rc<Node>],
+             node: &Rc<Node>,
+             write: bool) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if write {
+                print!("{}", args[0]);
+            }
+            else {
+                print!("{}", args[0].display());
+            }
+            return Ok(args[0].clone())
+        }
+
+        Err(EvalError::other(format!("{} takes only one argument, but got {}", operator(node), show_args(&args))))
+    }
+
+    // A relative path is taken to be relative to the file the `load` form
+    // is in, or the current directory if it isn't in one.
+    fn load(&self,
+            env: &mut Env,
+            args: &[Rc<Node>],
+            node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Node::Str(ref path) = *args[0] {
+                let path = match self.file_of(node) {
+                    Some(file) => Path::new(&*file).with_file_name(path).display().to_string(),
+                    None => path.clone(),
+                };
+                let source = fs::read_to_string(&path).map_err(|err| EvalError::other(
+                        format!("Failed to load {:?}: {}", path, err)))?;
+                let file = Rc::new(path.clone());
+                self.add_file(file.clone(), &source);
+                // Errors in the loaded file point into it rather than at `load`
+                return match self.eval_source(env, file, &source) {
+                    Ok(_) => Ok(Rc::new(Node::True)),
+                    Err(LispError::Eval(err)) => Err(err),
+                    Err(err) => Err(EvalError {
+                        span: err.span().cloned(), ..EvalError::other(format!("Failed to load {:?}: {}", path, err))
+                    }),
+                }
+            }
+        }
+
+        Err(EvalError::other(format!("`load` takes only a file name, but got {}", show_args(&args))))
+    }
+
+    /// Reads every form in `source`, which came from `file`, and evaluates
+    /// them in order. Nothing is evaluated if any of them fails to read.
+    /// Gives the value of the last form, or `None` if there's none.
+    pub fn eval_source(&self, env: &mut Env, file: Rc<String>, source: &str) -> Result<Option<Rc<Node>>, LispError> {
+        let tokens = Lexer::with_file(source, Some(file.clone())).with_truthiness(self.truthiness).tokenize()?;
+        let mut parser = Parser::with_file(tokens, Some(file));
+        let mut forms = Vec::new();
+        while !parser.is_eof() {
+            forms.push(parser.parse()?);
+        }
+        self.add_spans(parser.take_spans());
+
+        let mut result = None;
+        for form in forms {
+            result = Some(self.eval(env, form)?);
+        }
+        Ok(result)
     }
 
     fn setq(&self,
@@ -203,138 +1133,395 @@ impl Eval {
             args: &[Rc<Node>],
             node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
 
-        if args.len() % 2 == 0 {
+        if args.len().is_multiple_of(2) {
             let mut key = None;
             for arg in args {
                 if let Some(k) = key {
-                    let evalated_node = try!(self.eval(env, arg.clone()));
-                    env.insert(k, evalated_node);
+                    let evalated_node = self.eval(env, arg.clone())?;
+                    env.set(k, evalated_node);
                     key = None;
                 }
+                else if let Node::Symbol(name) = **arg {
+                    key = Some(name)
+                }
                 else {
-                    if let Node::Keyword(ref k) = *arg.clone() {
-                        key = Some(k.clone())
-                    }
-                    else {
-                        return Err(EvalError(format!(
-                                    "`setq` accepts only Node::Keyword as a key, but got {:?}", arg)));
-                    }
+                    return Err(EvalError::other(format!(
+                                "`setq` accepts only symbols as keys, but got {}", arg)));
                 }
             }
             return Ok(node.clone())
         }
 
-        Err(EvalError(
-                format!("`setq` takes only key value pairs, but got {:?}", args)))
+        Err(EvalError::other(
+                format!("`setq` takes only key value pairs, but got {}", show_args(args))))
     }
 
-    fn lambda(&self, env: &mut Env, args: &[Rc<Node>], node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
-        if args.len() == 2 {
-            match (&*args[0].clone(), &*args[1].clone()) {
-                (&Node::List(ref xs), &Node::List(ref body)) => {
-                    let mut fargs = Vec::new();
-                    let mut errors = Vec::new();
-
-                    for x in xs {
-                        match *x.clone() {
-                            Node::Keyword(ref kwd) => fargs.push(kwd.clone()),
-                            _ => errors.push(
-                                    EvalError(format!(
-                                        "The 2nd parameter of `lambda` should be a list of keywords, but got {:?}", args))
-                                ),
-                        }
-                    }
+    fn lambda(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+        if args.len() >= 2 {
+            if let Some(f) = function(env, None, &args[0], &args[1..]) {
+                return Ok(f)
+            }
+        }
 
-                    if ! errors.is_empty() {
-                        return Err(errors[0].clone())
-                    }
+        Err(EvalError::other(
+                format!("`lambda` takes only (params:list body...), but got {}", show_args(args))))
+    }
+
+    // `(defun name (params...) body...)` binds `name` in the current frame.
+    fn defun(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+        if args.len() >= 3 {
+            if let Node::Symbol(name) = *args[0] {
+                if let Some(f) = function(env, Some(name), &args[1], &args[2..]) {
+                    env.insert(name, f);
+                    return Ok(args[0].clone())
+                }
+            }
+        }
 
-                    // TODO: Avoid copying
-                    return Ok(Rc::new(Node::Func(fargs, body.clone())))
+        Err(EvalError::other(
+                format!("`defun` takes only (name:symbol params:list body...), but got {}", show_args(args))))
+    }
+
+    // `(define (name params...) body...)` is the same as `defun`, and
+    // `(define name value)` binds `name` to the value.
+    fn define(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+        if args.len() >= 2 {
+            match *args[0] {
+                Node::Pair(ref hd, ref params) => if let Node::Symbol(name) = **hd {
+                    if let Some(f) = function(env, Some(name), params, &args[1..]) {
+                        env.insert(name, f);
+                        return Ok(hd.clone())
+                    }
+                },
+                Node::Symbol(name) if args.len() == 2 => {
+                    let value = self.eval(env, args[1].clone())?;
+                    env.insert(name, value);
+                    return Ok(args[0].clone())
                 },
-                _ => ()
+                _ => (),
+            }
+        }
+
+        Err(EvalError::other(
+                format!("`define` takes only ((name:symbol params...) body...) or (name:symbol value), but got {}", show_args(args))))
+    }
+
+    fn documentation(&self,
+                     env: &mut Env,
+                     args: &[Rc<Node>],
+                     _node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
+
+        let args = self.eval_args(env, args)?;
+        if args.len() == 1 {
+            if let Node::Func(ref f) = *args[0] {
+                return Ok(Rc::new(match f.doc {
+                    Some(ref doc) => Node::Str(doc.clone()),
+                    None => Node::Nil,
+                }))
             }
         }
 
-        Err(EvalError(
-                format!("`lambda` takes only (name:keyword args:list body:list), but got {:?}", args)))
+        Err(EvalError::other(format!("`documentation` takes only a function, but got {}", show_args(&args))))
     }
 
-    fn call(&self, env: &mut Env, args: &[Rc<Node>], node: &Rc<Node>) -> Result<Rc<Node>, EvalError> {
-        match *node.clone() {
-            Node::Func(ref xs, ref body) => {
-                if xs.len() != args.len() {
-                    panic!("The numbers of argments don't match: expected({:?}), got({:?})", xs, args);
+    // `(let ((name value) ...) body...)` evaluates every value before
+    // binding any of them. `(let name ((name value) ...) body...)` also
+    // binds `name` to a function that runs the body again with new values,
+    // for loops.
+    fn let_form(&self, env: &mut Env, args: &[Rc<Node>], node: &Rc<Node>) -> Result<Tail, EvalError> {
+        if let Some(&Node::Symbol(name)) = args.first().map(|x| &**x) {
+            return self.named_let(env, name, &args[1..], node)
+        }
+        if args.len() >= 2 {
+            if let Some(bindings) = bindings(&args[0]) {
+                let mut values = Vec::new();
+                for (_, value) in &bindings {
+                    values.push(self.eval(env, value.clone())?);
+                }
+                let mut lenv = env.extend();
+                for ((name, _), value) in bindings.into_iter().zip(values) {
+                    lenv.insert(name, value);
                 }
+                return self.body(&mut lenv, &args[1..])
+            }
+        }
+
+        Err(EvalError::other(
+                format!("`let` takes only (bindings:list body...), but got {}", show_args(args))))
+    }
+
+    fn named_let(&self, env: &mut Env, name: Symbol, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        if args.len() >= 2 {
+            if let Some(bindings) = bindings(&args[0]) {
+                let mut values = Vec::new();
+                for (_, value) in &bindings {
+                    values.push(self.eval(env, value.clone())?);
+                }
+                let mut fenv = env.extend_recursive();
+                let f = Function {
+                    name: Some(name),
+                    params: bindings.iter().map(|x| x.0).collect(),
+                    doc: None,
+                    body: args[1..].to_vec(),
+                    env: fenv.clone(),
+                };
+                fenv.insert(name, Rc::new(Node::Func(Rc::new(f))));
+                let mut lenv = fenv.extend();
+                for ((name, _), value) in bindings.into_iter().zip(values) {
+                    lenv.insert(name, value);
+                }
+                return self.body(&mut lenv, &args[1..])
+            }
+        }
 
-                env.push_env();
+        Err(EvalError::other(
+                format!("Named `let` takes only (name:symbol bindings:list body...), but got {}", show_args(args))))
+    }
 
-                for (i, x) in xs.iter().enumerate() {
-                    let evaled_arg = try!(self.eval(env, args[i].clone()));
-                    env.insert(x.clone(), evaled_arg);
+    // Like `let`, but each value is evaluated with the bindings before it
+    // in scope.
+    fn let_star_form(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        if args.len() >= 2 {
+            if let Some(bindings) = bindings(&args[0]) {
+                let mut lenv = env.extend();
+                for (name, value) in bindings {
+                    let value = self.eval(&mut lenv, value)?;
+                    // A frame for each binding, so that a closure made for
+                    // one doesn't see the ones after it
+                    lenv = lenv.extend();
+                    lenv.insert(name, value);
                 }
+                return self.body(&mut lenv, &args[1..])
+            }
+        }
+
+        Err(EvalError::other(
+                format!("`let*` takes only (bindings:list body...), but got {}", show_args(args))))
+    }
+
+    // Like `let*`, but every value is evaluated with all of the bindings in
+    // scope, so that functions can refer to each other. A value that uses a
+    // binding after it before that one is made is an error.
+    fn letrec_form(&self, env: &mut Env, args: &[Rc<Node>], _node: &Rc<Node>) -> Result<Tail, EvalError> {
+        if args.len() >= 2 {
+            if let Some(bindings) = bindings(&args[0]) {
+                let mut lenv = env.extend_recursive();
+                for (name, value) in bindings {
+                    let value = self.eval(&mut lenv, value)?;
+                    lenv.insert(name, value);
+                }
+                return self.body(&mut lenv, &args[1..])
+            }
+        }
+
+        Err(EvalError::other(
+                format!("`letrec` takes only (bindings:list body...), but got {}", show_args(args))))
+    }
+
+    // Calls `node`, the value of the form `operator`.
+    fn call(&self, env: &mut Env, args: &[Rc<Node>], node: &Rc<Node>, operator: &Rc<Node>) -> Result<Tail, EvalError> {
+        if let Node::Func(ref f) = **node {
+            let xs = &f.params;
+            if xs.len() != args.len() {
+                let name = f.name.map_or(String::from("The function"), |x| format!("`{}`", x));
+                let params: Vec<_> = xs.iter().map(|x| x.name()).collect();
+                return Err(EvalError::new(
+                        EvalErrorKind::ArityMismatch { expected: xs.len(), got: args.len() },
+                        format!("{} takes {} argument{} ({}), but got {}",
+                                name, xs.len(), if xs.len() == 1 { "" } else { "s" }, params.join(" "), show_args(args))))
+            }
+
+            // Arguments are evaluated in the caller's environment, but the
+            // body only sees them on top of the environment the function
+            // was created in.
+            let mut evaled_args = Vec::new();
+            for arg in args {
+                evaled_args.push(self.eval(env, arg.clone())?);
+            }
+
+            let mut fenv = f.env.extend();
+            for (x, evaled_arg) in xs.iter().zip(evaled_args) {
+                fenv.insert(*x, evaled_arg);
+            }
 
-                let result = try!(self.eval(env, Rc::new(Node::List(body.clone()))));
+            return match self.body(&mut fenv, &f.body) {
+                Ok(Tail::Eval(env, node)) => Ok(Tail::Call(f.name, env, node)),
+                Ok(tail) => Ok(tail),
+                Err(err) => Err(err.traced(f.name)),
+            }
+        }
 
-                env.pop_env();
+        let operator_name = match **operator {
+            Node::Symbol(name) => format!("`{}`", name),
+            _ => operator.to_string(),
+        };
+        Err(self.locate(EvalError::other(format!("{} is {}, which isn't a function", operator_name, node)), operator))
+    }
 
-                return Ok(result)
+    // Evaluates the forms of a body in order, leaving the last one to be
+    // evaluated in tail position. An empty body gives `()`.
+    fn body(&self, env: &mut Env, body: &[Rc<Node>]) -> Result<Tail, EvalError> {
+        match body.split_last() {
+            Some((last, init)) => {
+                for x in init {
+                    self.eval(env, x.clone())?;
+                }
+                Ok(Tail::Eval(env.clone(), last.clone()))
             },
-            _ => ()
+            None => Ok(Tail::Return(Rc::new(Node::Nil))),
         }
+    }
 
-        Err(EvalError(
-                format!("Failed to call a function due to unexpected arguments: node is {:?} and arguments are {:?}",
-                        node, args)))
+    fn eval_args(&self, env: &mut Env, args: &[Rc<Node>]) -> Result<Vec<Rc<Node>>, EvalError> {
+        args.iter().map(|x| self.eval(env, x.clone())).collect()
     }
 
     pub fn eval(&self, env: &mut Env, node: Rc<Node>) -> Result<Rc<Node>, EvalError> {
-        match &*node.clone() {
-            &Node::Integer(_) => Ok(node),
-            &Node::Keyword(ref kwd) => {
-                let nd = match env.get(&kwd) {
-                    Some(x) => x,
-                    None => return Ok(Rc::new(Node::Keyword(kwd.clone()))),
-                };
-                self.eval(env, nd.clone())
+        let depth = self.depth.get();
+        if depth >= MAX_DEPTH {
+            return Err(self.locate(EvalError::new(
+                        EvalErrorKind::TooDeep, format!("Evaluation is nested more than {} deep", MAX_DEPTH)), &node))
+        }
+        self.depth.set(depth + 1);
+        let result = self.eval_loop(env, node);
+        self.depth.set(depth);
+        result
+    }
+
+    // Evaluates `node` and whatever it leaves in tail position.
+    fn eval_loop(&self, env: &mut Env, node: Rc<Node>) -> Result<Rc<Node>, EvalError> {
+        let mut env = env.clone();
+        let mut node = node;
+        // The function whose body `node` is the end of, if any. A call in
+        // tail position takes the place of the caller.
+        let mut function = None;
+        loop {
+            match self.eval_step(&mut env, node.clone()) {
+                Ok(Tail::Return(result)) => return Ok(result),
+                Ok(Tail::Eval(next_env, next_node)) => {
+                    env = next_env;
+                    node = next_node;
+                },
+                Ok(Tail::Call(name, next_env, next_node)) => {
+                    function = name;
+                    env = next_env;
+                    node = next_node;
+                },
+                Err(err) => return Err(self.locate(err, &node).traced(function)),
+            }
+        }
+    }
+
+    fn eval_step(&self, env: &mut Env, node: Rc<Node>) -> Result<Tail, EvalError> {
+        match *node {
+            Node::Integer(_) => Ok(Tail::Return(node.clone())),
+            // The value is returned as it is. It has been evaluated already,
+            // and a list in it is data rather than a call.
+            Node::Symbol(name) => match env.get(name) {
+                Some(x) => Ok(Tail::Return(x)),
+                None => Err(unbound(env, name, "Unbound variable")),
+            },
+            Node::Pair(ref hd, ref tl) => match tl.to_vec() {
+                Some(args) => self.eval_func(env, &node, hd, &args),
+                None => Err(EvalError::other(format!("Can't apply an improper list {}", node))),
             },
-            &Node::List(ref xs) => self.eval_func(env, &node, xs),
-            // TODO: Avoid copying
-            &Node::QuotedList(ref x) => Ok(Rc::new(Node::List(x.clone()))),
-            _ => Ok(node),
+            Node::Nil if self.truthiness == Truthiness::CommonLisp => Ok(Tail::Return(node.clone())),
+            Node::Nil => Err(EvalError::new(
+                    EvalErrorKind::EmptyApplication, String::from("Can't apply an empty list `()`"))),
+            _ => Ok(Tail::Return(node.clone())),
         }
     }
 
-    fn eval_func(&self, env: &mut Env, node: &Rc<Node>, xs: &Vec<Rc<Node>>) -> Result<Rc<Node>, EvalError> {
-        let (hd, tl) = xs.split_first().unwrap();
-        if let &Node::Keyword(ref kwd) = &*hd.clone() {
-            match kwd.as_str() {
-                "+" => self.calc_integer(env, &|a, i| a + i, tl, node),
-                "-" => self.calc_integer(env, &|a, i| a - i, tl, node),
-                "*" => self.calc_integer(env, &|a, i| a * i, tl, node),
-                "/" => self.calc_integer(env, &|a, i| a / i, tl, node),
+    fn eval_func(&self, env: &mut Env, node: &Rc<Node>, hd: &Rc<Node>, tl: &[Rc<Node>]) -> Result<Tail, EvalError> {
+        if let Node::Symbol(name) = **hd {
+            // Special forms can't be replaced, since their operands aren't
+            // evaluated like arguments
+            match name.name() {
+                "if" => return self.if_then_else(env, tl, node),
+                "when" => return self.when(env, tl, node, true),
+                "unless" => return self.when(env, tl, node, false),
+                "cond" => return self.cond_form(env, tl, node),
+                "case" => return self.case(env, tl, node),
+                "and" => return self.and(env, tl, node),
+                "or" => return self.or(env, tl, node),
+                "quote" => return self.quote(env, tl, node).map(Tail::Return),
+                "setq" => return self.setq(env, tl, node).map(Tail::Return),
+                "progn" | "begin" => return self.body(env, tl),
+                "lambda" => return self.lambda(env, tl, node).map(Tail::Return),
+                "defun" => return self.defun(env, tl, node).map(Tail::Return),
+                "define" => return self.define(env, tl, node).map(Tail::Return),
+                "let" => return self.let_form(env, tl, node),
+                "let*" => return self.let_star_form(env, tl, node),
+                "letrec" => return self.letrec_form(env, tl, node),
+                _ => (),
+            }
+            // Anything else the program binds takes the place of a builtin
+            // of the same name
+            if let Some(f) = env.get(name) {
+                return self.call(env, tl, &f, hd)
+            }
+            let result = match name.name() {
+                "+" => self.calc_number(env, &|a, i| Ok(a + i), tl, node),
+                "-" => self.calc_number(env, &|a, i| Ok(a - i), tl, node),
+                "*" => self.calc_number(env, &|a, i| Ok(a * i), tl, node),
+                "/" => self.calc_number(env, &|a, i| {
+                    let message = format!("Division by zero: {} / {}", a, i);
+                    a.checked_div(i).ok_or_else(|| EvalError::new(EvalErrorKind::DivisionByZero, message))
+                }, tl, node),
                 "=" => self.cond(env, &|a, i| a == i, tl, node),
                 ">" => self.cond(env, &|a, i| a > i, tl, node),
                 ">=" => self.cond(env, &|a, i| a >= i, tl, node),
                 "<" => self.cond(env, &|a, i| a < i, tl, node),
                 "<=" => self.cond(env, &|a, i| a <= i, tl, node),
                 "/=" => self.cond(env, &|a, i| a != i, tl, node),
-                "if" => self.if_then_else(env, tl, node),
+                "floor" => self.math(env, &|n| Ok(n.round_with(f64::floor)), tl, node),
+                "ceiling" => self.math(env, &|n| Ok(n.round_with(f64::ceil)), tl, node),
+                "round" => self.math(env, &|n| Ok(n.round_with(f64::round_ties_even)), tl, node),
+                "truncate" => self.math(env, &|n| Ok(n.round_with(f64::trunc)), tl, node),
+                "sqrt" => self.math(env, &|n| Ok(n.sqrt()), tl, node),
+                "exp" => self.math(env, &|n| Ok(n.inexact_with(f64::exp)), tl, node),
+                "log" => self.math(env, &|n| Ok(n.inexact_with(f64::ln)), tl, node),
+                "sin" => self.math(env, &|n| Ok(n.inexact_with(f64::sin)), tl, node),
+                "cos" => self.math(env, &|n| Ok(n.inexact_with(f64::cos)), tl, node),
+                "tan" => self.math(env, &|n| Ok(n.inexact_with(f64::tan)), tl, node),
+                "exact->inexact" => self.math(env, &|n| Ok(n.to_inexact()), tl, node),
+                "inexact->exact" => self.math(env, &|n| n.to_exact().ok_or_else(|| EvalError::other(
+                            format!("{} has no exact representation", n))), tl, node),
+                "expt" => self.expt(env, tl, node),
+                "not" => self.not(env, tl, node),
                 "car" => self.car(env, tl, node),
                 "cdr" => self.cdr(env, tl, node),
-                "setq" => self.setq(env, tl, node),
-                "lambda" => self.lambda(env, tl, node),
-                _ =>  {
-                    let f = match env.get(kwd) {
-                        Some(f) => f.clone(),
-                        None => return Err(EvalError(format!("Unknown keyword: {:?}", kwd)))
-                    };
-                    self.call(env, &tl, &f)
-                }
-            }
+                "cons" => self.cons(env, tl, node),
+                "list" => self.list(env, tl, node),
+                "null?" => self.is_null(env, tl, node),
+                "pair?" => self.is_pair(env, tl, node),
+                "length" => self.length(env, tl, node),
+                "append" => self.append(env, tl, node),
+                "reverse" => self.reverse(env, tl, node),
+                "list-ref" => self.list_ref(env, tl, node),
+                "nth" => self.nth(env, tl, node),
+                "last" => self.last(env, tl, node),
+                "string-length" => self.string_length(env, tl, node),
+                "substring" => self.substring(env, tl, node),
+                "string-append" => self.string_append(env, tl, node),
+                "string=" => self.string_cond(env, &|a, b| a == b, tl, node),
+                "string<" => self.string_cond(env, &|a, b| a < b, tl, node),
+                "string->number" => self.string_to_number(env, tl, node),
+                "number->string" => self.number_to_string(env, tl, node),
+                "write" => self.print(env, tl, node, true),
+                "display" => self.print(env, tl, node, false),
+                "load" => self.load(env, tl, node),
+                "documentation" => self.documentation(env, tl, node),
+                _ => return Err(self.locate(unbound(env, name, "Unknown function"), hd)),
+            };
+            result.map(Tail::Return)
+        }
+        else if let Node::Pair(..) = **hd {
+            let f = self.eval(env, hd.clone())?;
+            self.call(env, tl, &f, hd)
         }
         else {
-            Err(EvalError(format!("Unexpected node: {:?}", node)))
+            Err(self.locate(EvalError::other(format!("{} isn't a function", hd)), hd))
         }
     }
 }
@@ -349,13 +1536,13 @@ mod tests {
             Node::Integer(42),
             *Eval::new().eval(
                 &mut Env::new(),
-                Rc::new(Node::List(
+                Node::list(
                     vec![
-                        Rc::new(Node::Keyword(String::from("+"))),
+                        Rc::new(Node::Symbol(Symbol::intern("+"))),
                         Rc::new(Node::Integer(2)),
                         Rc::new(Node::Integer(40)),
                     ]
-                ))
+                )
             ).unwrap()
         );
 
@@ -363,19 +1550,19 @@ mod tests {
             Node::Integer(42),
             *Eval::new().eval(
                 &mut Env::new(),
-                Rc::new(Node::List(
+                Node::list(
                     vec![
-                        Rc::new(Node::Keyword(String::from("*"))),
+                        Rc::new(Node::Symbol(Symbol::intern("*"))),
                         Rc::new(Node::Integer(6)),
-                        Rc::new(Node::List(
+                        Node::list(
                             vec![
-                                Rc::new(Node::Keyword(String::from("-"))),
+                                Rc::new(Node::Symbol(Symbol::intern("-"))),
                                 Rc::new(Node::Integer(42)),
                                 Rc::new(Node::Integer(35)),
                             ]
-                        )),
+                        ),
                     ]
-                ))
+                )
             ).unwrap()
         );
 
@@ -383,23 +1570,26 @@ mod tests {
             Node::Integer(42),
             *Eval::new().eval(
                 &mut Env::new(),
-                Rc::new(Node::List(
+                Node::list(
                     vec![
-                        Rc::new(Node::Keyword(String::from("car"))),
-                        Rc::new(Node::QuotedList(
+                        Rc::new(Node::Symbol(Symbol::intern("car"))),
+                        Node::list(
                             vec![
-                                Rc::new(Node::Integer(42)),
-                                Rc::new(Node::Integer(-123)),
-                                Rc::new(Node::Integer(0)),
+                                Rc::new(Node::Symbol(Symbol::intern("quote"))),
+                                Node::list(vec![
+                                    Rc::new(Node::Integer(42)),
+                                    Rc::new(Node::Integer(-123)),
+                                    Rc::new(Node::Integer(0)),
+                                ]),
                             ]
-                        )),
+                        ),
                     ]
-                ))
+                )
             ).unwrap()
         );
 
         assert_eq!(
-            Node::QuotedList(
+            *Node::list(
                 vec![
                     Rc::new(Node::Integer(4)),
                     Rc::new(Node::Integer(2)),
@@ -407,78 +1597,194 @@ mod tests {
             ),
             *Eval::new().eval(
                 &mut Env::new(),
-                Rc::new(Node::List(
+                Node::list(
                     vec![
-                        Rc::new(Node::Keyword(String::from("cdr"))),
-                        Rc::new(Node::QuotedList(
+                        Rc::new(Node::Symbol(Symbol::intern("cdr"))),
+                        Node::list(
                             vec![
-                                Rc::new(Node::Integer(0)),
-                                Rc::new(Node::Integer(4)),
-                                Rc::new(Node::Integer(2)),
+                                Rc::new(Node::Symbol(Symbol::intern("quote"))),
+                                Node::list(vec![
+                                    Rc::new(Node::Integer(0)),
+                                    Rc::new(Node::Integer(4)),
+                                    Rc::new(Node::Integer(2)),
+                                ]),
                             ]
-                        )),
+                        ),
                     ]
-                ))
+                )
             ).unwrap()
         );
 
         let env0 = &mut Env::new();
-        env0.insert(String::from("x"), Rc::new(Node::Integer(40)));
+        env0.insert(Symbol::intern("x"), Rc::new(Node::Integer(40)));
         assert_eq!(
             Node::Integer(42),
             *Eval::new().eval(
                 env0,
-                Rc::new(Node::List(
+                Node::list(
                     vec![
-                        Rc::new(Node::Keyword(String::from("+"))),
+                        Rc::new(Node::Symbol(Symbol::intern("+"))),
                         Rc::new(Node::Integer(2)),
-                        Rc::new(Node::Keyword(String::from("x")))
+                        Rc::new(Node::Symbol(Symbol::intern("x")))
                     ]
-                ))
+                )
             ).unwrap()
         );
 
         let env1 = &mut Env::new();
         Eval::new().eval(
             env1,
-            Rc::new(Node::List(
+            Node::list(
                 vec![
-                    Rc::new(Node::Keyword(String::from("setq"))),
-                    Rc::new(Node::Keyword(String::from("add"))),
-                    Rc::new(Node::List(
+                    Rc::new(Node::Symbol(Symbol::intern("setq"))),
+                    Rc::new(Node::Symbol(Symbol::intern("add"))),
+                    Node::list(
                         vec![
-                            Rc::new(Node::Keyword(String::from("lambda"))),
-                            Rc::new(Node::List(
+                            Rc::new(Node::Symbol(Symbol::intern("lambda"))),
+                            Node::list(
                                 vec![
-                                    Rc::new(Node::Keyword(String::from("a"))),
-                                    Rc::new(Node::Keyword(String::from("b"))),
+                                    Rc::new(Node::Symbol(Symbol::intern("a"))),
+                                    Rc::new(Node::Symbol(Symbol::intern("b"))),
                                 ]
-                            )),
-                            Rc::new(Node::List(
+                            ),
+                            Node::list(
                                 vec![
-                                    Rc::new(Node::Keyword(String::from("+"))),
-                                    Rc::new(Node::Keyword(String::from("a"))),
-                                    Rc::new(Node::Keyword(String::from("b"))),
+                                    Rc::new(Node::Symbol(Symbol::intern("+"))),
+                                    Rc::new(Node::Symbol(Symbol::intern("a"))),
+                                    Rc::new(Node::Symbol(Symbol::intern("b"))),
                                 ]
-                            )),
+                            ),
                         ]
-                    ))
+                    )
                 ]
-            ))
-        );
+            )
+        ).unwrap();
 
         assert_eq!(
             Node::Integer(42),
             *Eval::new().eval(
                 env1,
-                Rc::new(Node::List(
+                Node::list(
                     vec![
-                        Rc::new(Node::Keyword(String::from("add"))),
+                        Rc::new(Node::Symbol(Symbol::intern("add"))),
                         Rc::new(Node::Integer(40)),
                         Rc::new(Node::Integer(2)),
                     ]
-                ))
+                )
             ).unwrap()
         );
     }
+
+    #[test]
+    fn env_after_error() {
+        let env = &mut Env::new();
+        env.insert(Symbol::intern("a"), Rc::new(Node::Integer(1)));
+        env.insert(Symbol::intern("add"), Rc::new(Node::Func(Rc::new(Function {
+                    name: Some(Symbol::intern("add")),
+                    params: vec![Symbol::intern("a"), Symbol::intern("b")],
+                    doc: None,
+                    body: vec![Node::list(vec![
+                        Rc::new(Node::Symbol(Symbol::intern("+"))),
+                        Rc::new(Node::Symbol(Symbol::intern("a"))),
+                        Rc::new(Node::Symbol(Symbol::intern("b"))),
+                    ])],
+                    env: Env::new(),
+                }))));
+        // Every name bound and what it's bound to
+        let bindings = |env: &Env| {
+            let mut names = env.names();
+            names.sort_by_key(|x| x.name());
+            names.into_iter().map(|x| (x, env.get(x))).collect::<Vec<_>>()
+        };
+        let before = bindings(env);
+
+        // Fails while evaluating the body, after the arguments are bound
+        assert!(Eval::new().eval(
+            env,
+            Node::list(
+                vec![
+                    Rc::new(Node::Symbol(Symbol::intern("add"))),
+                    Rc::new(Node::Integer(40)),
+                    Rc::new(Node::Str(String::from("2"))),
+                ]
+            )
+        ).is_err());
+
+        assert_eq!(before, bindings(env));
+        assert_eq!(Some(Rc::new(Node::Integer(1))), env.get(Symbol::intern("a")));
+        assert_eq!(None, env.get(Symbol::intern("b")));
+    }
+
+    #[test]
+    fn recursive_frames() {
+        let eval = Eval::new();
+        let env = &mut Env::new();
+        let run = |env: &mut Env, s: &str| {
+            let tokens = Lexer::new(s).tokenize().unwrap();
+            eval.eval(env, Parser::new(tokens).parse().unwrap()).unwrap()
+        };
+        run(env, "(defun count (n) (let loop ((i 0)) (if (< i n) (loop (+ i 1)) i)))");
+        run(env, "(defun even (n) (letrec ((ev? (lambda (n) (if (= n 0) #t (od? (- n 1)))))
+                                      (od? (lambda (n) (if (= n 0) #f (ev? (- n 1))))))
+                                (ev? n)))");
+
+        // Each frame made on top of `env` holds it, so its count goes up
+        // for every one that's never freed
+        let frames = Rc::strong_count(&env.vars);
+        for _ in 0..100 {
+            assert_eq!(Node::Integer(10), *run(env, "(count 10)"));
+            assert_eq!(Node::True, *run(env, "(even 10)"));
+        }
+        assert_eq!(frames, Rc::strong_count(&env.vars));
+    }
+
+    #[test]
+    fn extend() {
+        let env = &mut Env::new();
+        env.insert(Symbol::intern("x"), Rc::new(Node::Integer(1)));
+
+        let mut inner = env.extend();
+        inner.insert(Symbol::intern("x"), Rc::new(Node::Integer(2)));
+        inner.insert(Symbol::intern("y"), Rc::new(Node::Integer(3)));
+        assert_eq!(2, inner.depth());
+        assert_eq!(Some(Rc::new(Node::Integer(2))), inner.get(Symbol::intern("x")));
+
+        assert_eq!(1, env.depth());
+        assert_eq!(Some(Rc::new(Node::Integer(1))), env.get(Symbol::intern("x")));
+        assert_eq!(None, env.get(Symbol::intern("y")));
+    }
+
+    #[test]
+    fn suggest() {
+        assert_eq!(0, edit_distance("fib", "fib"));
+        assert_eq!(1, edit_distance("x1", "x"));
+        assert_eq!(1, edit_distance("lenght", "length"));
+        assert_eq!(3, edit_distance("kitten", "sitting"));
+        assert_eq!(3, edit_distance("", "abc"));
+        assert_eq!(1, edit_distance("\u{3042}a", "\u{3044}a"));
+
+        let names: Vec<Symbol> = ["x", "y", "counter", "count", "fib"].iter().map(|x| Symbol::intern(x)).collect();
+        let suggest = |x| super::suggest(Symbol::intern(x), &names).map(|x| x.name());
+        assert_eq!(Some("x"), suggest("x1"));
+        assert_eq!(Some("count"), suggest("coutn"));
+        assert_eq!(Some("counter"), suggest("countre"));
+        // Ties go to the name that sorts first
+        assert_eq!(Some("count"), suggest("countr"));
+        assert_eq!(Some("fib"), suggest("fbi"));
+        assert_eq!(None, suggest("fib"));
+        assert_eq!(None, suggest("zzz"));
+        assert_eq!(None, suggest("z"));
+    }
+
+    #[test]
+    fn builtins() {
+        // Every name in `BUILTINS` is one `eval_func` knows, so that it's
+        // never suggested for a typo only to be unknown itself
+        for name in BUILTINS {
+            let form = Node::list(vec![Rc::new(Node::Symbol(Symbol::intern(name)))]);
+            if let Err(err) = Eval::new().eval(&mut Env::new(), form) {
+                assert!(!matches!(err.kind, EvalErrorKind::UnboundVariable { .. }), "{}", name);
+            }
+        }
+    }
 }
@@ -1,261 +1,730 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::rc::Rc;
 use std::str::Chars;
+use bigint::BigInt;
+use span::Span;
+use truthiness::Truthiness;
 
-#[derive(PartialEq, Debug)]
+/// `index` and `len` count chars, and `byte` is the offset of the first one
+/// in the input. `line` and `column` start from 1, and columns count chars.
+#[derive(PartialEq, Debug, Clone)]
 pub struct ExtendedToken {
     pub token: Token,
     pub index: usize,
     pub len: usize,
+    pub byte: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
 impl ExtendedToken {
-    fn new(token: Token, index: usize, len: usize) -> Self {
-        ExtendedToken { token: token, index: index, len: len }
+    fn new(token: Token, start: Position, len: usize) -> Self {
+        ExtendedToken { token, index: start.index, len, byte: start.byte, line: start.line, column: start.column }
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum Token {
     LParen,
     RParen,
     Quote,
     Integer(i64),
+    BigInt(BigInt),
+    Float(f64),
+    Str(String),
+    Symbol(String),
+    /// `:name`, without the colon.
     Keyword(String),
+    /// `#t`
+    True,
+    /// `#f`
+    False,
+    /// `nil`, the empty list. `t` and `nil` are only read like this with
+    /// `Truthiness::CommonLisp`.
+    Nil,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    index: usize,
+    byte: usize,
+    line: usize,
+    column: usize,
 }
 
 #[derive(Debug)]
 struct Context<'a> {
-    cs: Chars<'a>,
-    read_ahead: String,
-    index: usize,
+    cs: Peekable<Chars<'a>>,
+    pos: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexerErrorKind {
+    /// The input ended inside a string.
+    UnterminatedString,
+    /// The input ended inside a `#| ... |#` comment, or before the datum
+    /// after a `#;` was complete.
+    UnterminatedComment,
+    Other,
 }
 
 #[derive(Debug)]
-pub struct LexerError(String);
+pub struct LexerError {
+    pub kind: LexerErrorKind,
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
 impl <'a> Context<'a> {
     pub fn new(s: &'a str) -> Self {
-        Context { cs: s.chars(), read_ahead: String::new(), index: 0 }
+        Context { cs: s.chars().peekable(), pos: Position { index: 0, byte: 0, line: 1, column: 1 } }
     }
 
     pub fn next(&mut self) -> Option<char> {
-        let c = 
-            if self.read_ahead.is_empty() {
-                self.cs.next()
-            }
-            else {
-                Some(self.read_ahead.remove(0))
-            };
-
-        self.index += 1;
+        let c = self.cs.next()?;
+        self.pos.index += 1;
+        self.pos.byte += c.len_utf8();
+        if c == '\n' {
+            self.pos.line += 1;
+            self.pos.column = 1;
+        }
+        else {
+            self.pos.column += 1;
+        }
+        Some(c)
+    }
 
-        c
+    pub fn peek(&mut self) -> Option<char> {
+        self.cs.peek().cloned()
     }
 
-    pub fn return_char(&mut self, c: char) {
-        self.read_ahead.push(c);
-        self.index -= 1;
+    // Consumes the next char if it's `c`.
+    pub fn next_if(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.next();
+            return true
+        }
+        false
     }
 
-    pub fn pos(&self) -> usize {
-        self.index
+    pub fn pos(&self) -> Position {
+        self.pos
     }
 }
 
 pub struct Lexer<'a> {
-    ctx: Context<'a>
+    ctx: Context<'a>,
+    file: Option<Rc<String>>,
+    truthiness: Truthiness,
 }
 
 impl <'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
-        Lexer { ctx: Context::new(input) }
+        Lexer::with_file(input, None)
+    }
+
+    /// `file` ends up in the spans of errors, for telling sources apart.
+    pub fn with_file(input: &'a str, file: Option<Rc<String>>) -> Self {
+        Lexer { ctx: Context::new(input), file, truthiness: Truthiness::default() }
+    }
+
+    /// With `Truthiness::Scheme`, `t` and `nil` are read as ordinary
+    /// symbols.
+    pub fn with_truthiness(mut self, truthiness: Truthiness) -> Self {
+        self.truthiness = truthiness;
+        self
+    }
+
+    // An error covering everything from `start` up to the current position.
+    fn error(&self, message: String, start: Position) -> LexerError {
+        LexerError {
+            kind: LexerErrorKind::Other,
+            message,
+            span: Span::new(self.file.clone(), start.line, start.column, start.index, self.ctx.pos().index - start.index),
+        }
+    }
+
+    // A token covering everything from `start` up to the current position.
+    fn token(&self, token: Token, start: Position) -> ExtendedToken {
+        ExtendedToken::new(token, start, self.ctx.pos().index - start.index)
     }
 
     pub fn tokenize(&mut self) -> Result<Vec<ExtendedToken>, LexerError> {
         let mut tokens = Vec::new();
-        while let Some(c) = self.ctx.next() {
-            let pos_before_consume = self.ctx.pos() - 1;
-            if " \t\n".contains(c) {
-            }
-            else if c == '(' {
-                tokens.push(ExtendedToken::new(Token::LParen, pos_before_consume, 1));
-            }
-            else if c == ')' {
-                tokens.push(ExtendedToken::new(Token::RParen, pos_before_consume, 1));
-            }
-            else if c == '\'' {
-                tokens.push(ExtendedToken::new(Token::Quote, pos_before_consume, 1));
-            }
-            else if c == '+' {
-                tokens.push(ExtendedToken::new(Token::Keyword(String::from("+")), pos_before_consume, 1));
-            }
-            else if c == '-' {
-                tokens.push(ExtendedToken::new(Token::Keyword(String::from("-")), pos_before_consume, 1));
-            }
-            else if c == '*' {
-                tokens.push(ExtendedToken::new(Token::Keyword(String::from("*")), pos_before_consume, 1));
-            }
-            else if c == '/' {
-                if let Some(c) = self.ctx.next() {
-                    if c == '=' {
-                        tokens.push(ExtendedToken::new(Token::Keyword(String::from("/=")), pos_before_consume, 2));
-                    }
-                    else {
-                        tokens.push(ExtendedToken::new(Token::Keyword(String::from("/")), pos_before_consume, 1));
-                        self.ctx.return_char(c);
-                    }
+        while let Some(token) = self.next_token()? {
+            tokens.push(token);
+        }
+        Ok(tokens)
+    }
+
+    // Skips whitespace and comments, and reads the token after them.
+    fn next_token(&mut self) -> Result<Option<ExtendedToken>, LexerError> {
+        loop {
+            let start = self.ctx.pos();
+            let c = match self.ctx.next() {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+            let token =
+                if c.is_whitespace() {
+                    continue;
                 }
-                else {
-                    tokens.push(ExtendedToken::new(Token::Keyword(String::from("/")), pos_before_consume, 1));
+                else if c == ';' {
+                    while self.ctx.next().is_some_and(|x| x != '\n') {}
+                    continue;
                 }
-            }
-            else if c == '=' {
-                tokens.push(ExtendedToken::new(Token::Keyword(String::from("=")), pos_before_consume, 1));
-            }
-            else if c == '>' {
-                if let Some(c) = self.ctx.next() {
-                    if c == '=' {
-                        tokens.push(ExtendedToken::new(Token::Keyword(String::from(">=")), pos_before_consume, 2));
-                    }
-                    else {
-                        tokens.push(ExtendedToken::new(Token::Keyword(String::from(">")), pos_before_consume, 1));
-                        self.ctx.return_char(c);
-                    }
+                else if c == '#' && self.ctx.next_if('|') {
+                    self.skip_block_comment(start)?;
+                    continue;
                 }
-                else {
-                    tokens.push(ExtendedToken::new(Token::Keyword(String::from(">")), pos_before_consume, 1));
+                else if c == '#' && self.ctx.next_if(';') {
+                    self.skip_datum(start)?;
+                    continue;
                 }
-            }
-            else if c == '<' {
-                if let Some(c) = self.ctx.next() {
-                    if c == '=' {
-                        tokens.push(ExtendedToken::new(Token::Keyword(String::from("<=")), pos_before_consume, 2));
-                    }
-                    else {
-                        tokens.push(ExtendedToken::new(Token::Keyword(String::from("<")), pos_before_consume, 1));
-                        self.ctx.return_char(c);
-                    }
+                else if c == '(' {
+                    Token::LParen
                 }
-                else {
-                    tokens.push(ExtendedToken::new(Token::Keyword(String::from("<")), pos_before_consume, 1));
+                else if c == ')' {
+                    Token::RParen
                 }
-            }
-            else if c.is_alphanumeric() {
-                let mut s = String::new();
-                s.push(c);
-                while let Some(c) = self.ctx.next() {
-                    if c.is_alphanumeric() || c == '-' {
-                        s.push(c);
-                        continue;
-                    }
-                    self.ctx.return_char(c);
-                    break;
+                else if c == '\'' {
+                    Token::Quote
                 }
-                let len = s.len();
-                if s.chars().all(|x| x.is_digit(10)) {
-                    tokens.push(
-                        ExtendedToken::new(Token::Integer(s.parse().unwrap()), pos_before_consume, len));
+                else if c == '"' {
+                    Token::Str(self.read_string(start)?)
                 }
-                else {
-                    tokens.push(ExtendedToken::new(Token::Keyword(s), pos_before_consume, len));
+                else if is_reserved(c) {
+                    return Err(self.error(format!("Unexpected charactor: [{}]", c), start))
                 }
+                // Anything else is left for syntax like `#|` and `#;`
+                else if c == '#' {
+                    match self.read_word(c).as_str() {
+                        "#t" => Token::True,
+                        "#f" => Token::False,
+                        _ => return Err(self.error(format!("Unexpected charactor: [{}]", c), start)),
+                    }
+                }
+                else {
+                    let s = self.read_word(c);
+                    word_token(s, self.truthiness)
+                };
+            return Ok(Some(self.token(token, start)))
+        }
+    }
+
+    // Skips the rest of a `#| ... |#` comment that starts at `start`. They
+    // can be nested.
+    fn skip_block_comment(&mut self, start: Position) -> Result<(), LexerError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.ctx.next() {
+                Some('|') if self.ctx.next_if('#') => depth -= 1,
+                Some('#') if self.ctx.next_if('|') => depth += 1,
+                Some(_) => (),
+                None => return Err(LexerError {
+                    kind: LexerErrorKind::UnterminatedComment,
+                    ..self.error(String::from("Unterminated block comment"), start)
+                }),
             }
-            else {
-                return Err(LexerError(format!("Unexpected charactor: [{}] ({:?})", c, self.ctx)));
+        }
+        Ok(())
+    }
+
+    // Skips the complete datum after a `#;` at `start`. Comments inside it,
+    // including other `#;`s, are skipped along the way by `next_token`.
+    fn skip_datum(&mut self, start: Position) -> Result<(), LexerError> {
+        let mut depth = 0;
+        loop {
+            let token = match self.next_token()? {
+                Some(token) => token.token,
+                None => return Err(LexerError {
+                    kind: LexerErrorKind::UnterminatedComment,
+                    ..self.error(String::from("`#;` should be followed by a datum"), start)
+                }),
+            };
+            match token {
+                Token::LParen => depth += 1,
+                Token::RParen if depth == 0 =>
+                    return Err(self.error(String::from("`#;` should be followed by a datum"), start)),
+                Token::RParen => depth -= 1,
+                // The quoted datum is still to come
+                Token::Quote => continue,
+                _ => (),
+            }
+            if depth == 0 {
+                return Ok(())
             }
         }
-        Ok(tokens)
     }
+
+    fn read_word(&mut self, first: char) -> String {
+        let mut s = String::new();
+        s.push(first);
+        while let Some(c) = self.ctx.peek() {
+            if is_delimiter(c) {
+                break;
+            }
+            s.push(c);
+            self.ctx.next();
+        }
+        s
+    }
+
+    // `start` is where the opening `"` is.
+    fn read_string(&mut self, start: Position) -> Result<String, LexerError> {
+        let mut s = String::new();
+        loop {
+            let escape = self.ctx.pos();
+            match self.ctx.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.ctx.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('u') => s.push(self.read_unicode_escape(escape)?),
+                    Some(c) => return Err(self.error(format!("Unknown escape sequence: [\\{}]", c), escape)),
+                    None => break,
+                },
+                Some(c) => s.push(c),
+                None => break,
+            }
+        }
+        Err(LexerError { kind: LexerErrorKind::UnterminatedString, ..self.error(String::from("Unterminated string"), start) })
+    }
+
+    // Reads the `{...}` part of a `\u{...}` escape that starts at `start`.
+    fn read_unicode_escape(&mut self, start: Position) -> Result<char, LexerError> {
+        if self.ctx.next() != Some('{') {
+            return Err(self.error(String::from("`\\u` should be followed by `{`"), start));
+        }
+        let mut hex = String::new();
+        loop {
+            match self.ctx.next() {
+                Some('}') => break,
+                Some(c) if c.is_ascii_hexdigit() && hex.len() < 6 => hex.push(c),
+                _ => return Err(self.error(String::from("Invalid unicode escape"), start)),
+            }
+        }
+        match u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32) {
+            Some(c) => Ok(c),
+            None => Err(self.error(format!("Invalid unicode escape: [{}]", hex), start)),
+        }
+    }
+}
+
+// Ends a symbol or a number.
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()\"';".contains(c) || is_reserved(c)
+}
+
+// Syntax of other Lisps that isn't supported, like `|a b|` symbols and the
+// `` `(a ,b) `` quasiquote, so it can't be mistaken for part of a symbol.
+fn is_reserved(c: char) -> bool {
+    "|`,[]{}".contains(c)
+}
+
+// A word is a number if it reads as one, with an optional sign, a keyword if
+// it starts with `:`, and a symbol otherwise. So `-`, `-foo`, `1+` and `1.2.3`
+// are all symbols. `t` and `nil` are the same as `#t` and `()` in Common
+// Lisp.
+fn word_token(s: String, truthiness: Truthiness) -> Token {
+    if truthiness == Truthiness::CommonLisp {
+        match s.as_str() {
+            "t" => return Token::True,
+            "nil" => return Token::Nil,
+            _ => (),
+        }
+    }
+    if let Some(token) = number_token(&s) {
+        return token
+    }
+    match s.strip_prefix(':') {
+        Some(name) if !name.is_empty() => Token::Keyword(String::from(name)),
+        _ => Token::Symbol(s),
+    }
+}
+
+/// The number `s` is spelled as, if it's exactly one number literal, with
+/// nothing around it.
+pub fn number_token(s: &str) -> Option<Token> {
+    let unsigned = s.strip_prefix(['-', '+']).unwrap_or(s);
+    if !unsigned.is_empty() && unsigned.chars().all(|x| x.is_ascii_digit()) {
+        return Some(match s.parse() {
+            Ok(i) => Token::Integer(i),
+            Err(_) => Token::BigInt(s.parse().unwrap()),
+        })
+    }
+    // `parse` would also take words like `inf` and `NaN`, so those are
+    // spelled like in Scheme instead
+    match s {
+        "+inf.0" => return Some(Token::Float(f64::INFINITY)),
+        "-inf.0" => return Some(Token::Float(f64::NEG_INFINITY)),
+        "+nan.0" => return Some(Token::Float(f64::NAN)),
+        _ => (),
+    }
+    let digits = unsigned.strip_prefix('.').unwrap_or(unsigned);
+    if digits.starts_with(|x: char| x.is_ascii_digit()) {
+        return s.parse().ok().map(Token::Float)
+    }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // For single-line ASCII input, where chars, bytes and columns line up.
+    fn token(token: Token, index: usize, len: usize) -> ExtendedToken {
+        ExtendedToken { token, index, len, byte: index, line: 1, column: index + 1 }
+    }
+
     #[test]
     fn tokenize() {
         assert_eq!(
-            vec!(ExtendedToken::new(Token::LParen, 0, 1)),
+            vec!(token(Token::LParen, 0, 1)),
             Lexer::new("(").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::LParen, 2, 1)),
+            vec!(token(Token::LParen, 2, 1)),
             Lexer::new("  (   ").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::RParen, 0, 1)),
+            vec!(token(Token::RParen, 0, 1)),
             Lexer::new(")").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::RParen, 2, 1)),
+            vec!(token(Token::RParen, 2, 1)),
             Lexer::new("  )   ").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Quote, 0, 1)),
+            vec!(token(Token::Quote, 0, 1)),
             Lexer::new("'").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Integer(1234), 0, 4)),
+            vec!(token(Token::Integer(1234), 0, 4)),
             Lexer::new("1234").tokenize().unwrap());
 
         assert_eq!(
             vec!(
-                ExtendedToken::new(Token::LParen, 0, 1),
-                ExtendedToken::new(Token::Integer(0), 1, 1),
-                ExtendedToken::new(Token::RParen, 2, 1)
+                token(Token::LParen, 0, 1),
+                token(Token::Integer(0), 1, 1),
+                token(Token::RParen, 2, 1)
             ),
             Lexer::new("(0)").tokenize().unwrap());
 
         assert_eq!(
             vec!(
-                ExtendedToken::new(Token::Quote , 0, 1),
-                ExtendedToken::new(Token::LParen, 1, 1),
-                ExtendedToken::new(Token::Integer(0), 2, 1),
-                ExtendedToken::new(Token::RParen, 3, 1)
+                token(Token::Quote , 0, 1),
+                token(Token::LParen, 1, 1),
+                token(Token::Integer(0), 2, 1),
+                token(Token::RParen, 3, 1)
             ),
             Lexer::new("'(0)").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("defun")), 0, 5)),
+            vec!(token(Token::Symbol(String::from("defun")), 0, 5)),
             Lexer::new("defun").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("+")), 0, 1)),
+            vec!(token(Token::Symbol(String::from("+")), 0, 1)),
             Lexer::new("+").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("-")), 0, 1)),
+            vec!(token(Token::Symbol(String::from("-")), 0, 1)),
             Lexer::new("-").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("*")), 0, 1)),
+            vec!(token(Token::Symbol(String::from("*")), 0, 1)),
             Lexer::new("*").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("/")), 0, 1)),
+            vec!(token(Token::Symbol(String::from("/")), 0, 1)),
             Lexer::new("/").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("=")), 0, 1)),
+            vec!(token(Token::Symbol(String::from("=")), 0, 1)),
             Lexer::new("=").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("/=")), 0, 2)),
+            vec!(token(Token::Symbol(String::from("/=")), 0, 2)),
             Lexer::new("/=").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from(">")), 0, 1)),
+            vec!(token(Token::Symbol(String::from(">")), 0, 1)),
             Lexer::new(">").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from(">=")), 0, 2)),
+            vec!(token(Token::Symbol(String::from(">=")), 0, 2)),
             Lexer::new(">=").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("<")), 0, 1)),
+            vec!(token(Token::Symbol(String::from("<")), 0, 1)),
             Lexer::new("<").tokenize().unwrap());
 
         assert_eq!(
-            vec!(ExtendedToken::new(Token::Keyword(String::from("<=")), 0, 2)),
+            vec!(token(Token::Symbol(String::from("<=")), 0, 2)),
             Lexer::new("<=").tokenize().unwrap());
     }
+
+    #[test]
+    fn tokenize_signed() {
+        assert_eq!(
+            vec!(token(Token::Integer(-5), 0, 2)),
+            Lexer::new("-5").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Integer(7), 0, 2)),
+            Lexer::new("+7").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Integer(0), 0, 2)),
+            Lexer::new("-0").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Integer(i64::MIN), 0, 20)),
+            Lexer::new("-9223372036854775808").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(
+                token(Token::LParen, 0, 1),
+                token(Token::Integer(-1), 1, 2),
+                token(Token::Integer(2), 4, 1),
+                token(Token::RParen, 5, 1)
+            ),
+            Lexer::new("(-1 2)").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(
+                token(Token::LParen, 0, 1),
+                token(Token::Symbol(String::from("-")), 1, 1),
+                token(Token::Integer(5), 3, 1),
+                token(Token::RParen, 4, 1)
+            ),
+            Lexer::new("(- 5)").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(
+                token(Token::LParen, 0, 1),
+                token(Token::Symbol(String::from("+")), 1, 1),
+                token(Token::RParen, 2, 1)
+            ),
+            Lexer::new("(+)").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Symbol(String::from("-foo")), 0, 4)),
+            Lexer::new("-foo").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Symbol(String::from("1+")), 0, 2)),
+            Lexer::new("1+").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Symbol(String::from("+-1")), 0, 3)),
+            Lexer::new("+-1").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Symbol(String::from("-5x")), 0, 3)),
+            Lexer::new("-5x").tokenize().unwrap());
+    }
+
+    #[test]
+    fn tokenize_float() {
+        assert_eq!(
+            vec!(token(Token::Float(2.75), 0, 4)),
+            Lexer::new("2.75").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Float(1e-9), 0, 4)),
+            Lexer::new("1e-9").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Float(2.5e10), 0, 7)),
+            Lexer::new("2.5E+10").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(
+                token(Token::LParen, 0, 1),
+                token(Token::Float(-0.5), 1, 4),
+                token(Token::RParen, 5, 1)
+            ),
+            Lexer::new("(-0.5)").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(
+                token(Token::LParen, 0, 1),
+                token(Token::Symbol(String::from("-")), 1, 1),
+                token(Token::Float(0.5), 3, 3),
+                token(Token::RParen, 6, 1)
+            ),
+            Lexer::new("(- 0.5)").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Symbol(String::from("1.2.3")), 0, 5)),
+            Lexer::new("1.2.3").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::BigInt("-99999999999999999999".parse().unwrap()), 0, 21)),
+            Lexer::new("-99999999999999999999").tokenize().unwrap());
+    }
+
+    #[test]
+    fn tokenize_string() {
+        assert_eq!(
+            vec!(token(Token::Str(String::from("hello")), 0, 7)),
+            Lexer::new("\"hello\"").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(
+                token(Token::LParen, 0, 1),
+                token(Token::Str(String::new()), 1, 2),
+                token(Token::RParen, 3, 1)
+            ),
+            Lexer::new("(\"\")").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Str(String::from("a\nb\t\"c\"\\")), 0, 15)),
+            Lexer::new("\"a\\nb\\t\\\"c\\\"\\\\\"").tokenize().unwrap());
+
+        assert_eq!(
+            vec!(token(Token::Str(String::from("\u{3042}A")), 0, 16)),
+            Lexer::new("\"\\u{3042}\\u{41}\"").tokenize().unwrap());
+
+        let kind = |s| Lexer::new(s).tokenize().unwrap_err().kind;
+        assert_eq!(LexerErrorKind::UnterminatedString, kind("\"abc"));
+        assert_eq!(LexerErrorKind::UnterminatedString, kind("(a \"b\nc\\"));
+        assert_eq!(LexerErrorKind::Other, kind("\"\\q"));
+        assert!(Lexer::new("\"\\q\"").tokenize().is_err());
+        assert!(Lexer::new("\"\\u{d800}\"").tokenize().is_err());
+        assert!(Lexer::new("\"\\u41\"").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_symbol() {
+        let tokens = |s| Lexer::new(s).tokenize().unwrap().into_iter().map(|x| x.token).collect::<Vec<_>>();
+        let symbol = |s: &str| Token::Symbol(String::from(s));
+
+        for s in &["list->vector", "null?", "set-car!", "*global*", "a.b", "<=>", "%tmp", "a#b", "...",
+                   "->x", "1+", "-", "+", "\u{3042}", "x1", "inf", "-nan", "1e", "+.", "."] {
+            assert_eq!(vec![symbol(s)], tokens(s), "{}", s);
+        }
+        assert_eq!(vec![Token::Keyword(String::from("mode"))], tokens(":mode"));
+        assert_eq!(vec![Token::Keyword(String::from(":a"))], tokens("::a"));
+        assert_eq!(vec![symbol(":"), symbol("a:b")], tokens(": a:b"));
+        assert_eq!(vec![Token::Float(0.5)], tokens(".5"));
+        assert_eq!(vec![Token::Float(-0.5)], tokens("-.5"));
+        assert_eq!(vec![Token::Float(1.0)], tokens("1."));
+        assert_eq!(vec![Token::Float(f64::INFINITY), Token::Float(f64::NEG_INFINITY)], tokens("+inf.0 -inf.0"));
+        match tokens("+nan.0")[..] {
+            [Token::Float(x)] => assert!(x.is_nan()),
+            ref x => panic!("{:?}", x),
+        }
+
+        assert_eq!(
+            vec![Token::LParen, symbol("a"), Token::RParen, symbol("b"), Token::Quote, symbol("c")],
+            tokens("(a)b'c"));
+        assert_eq!(
+            vec![symbol("a"), Token::Str(String::from("b")), symbol("c")],
+            tokens("a\"b\"c"));
+        assert_eq!(vec![symbol("a"), symbol("b")], tokens("a;c\r\nb"));
+        assert_eq!(vec![symbol("a"), symbol("b")], tokens("a\r\n\tb"));
+
+        for s in &["|x|", "a|b", "`a", "'(a ,b)", ",@a", "[a]", "a]", "{a}", "|#"] {
+            assert!(Lexer::new(s).tokenize().is_err(), "{}", s);
+        }
+        assert_eq!(Span::new(None, 1, 2, 1, 1), Lexer::new("a|b|").tokenize().unwrap_err().span);
+        assert_eq!(vec![Token::Str(String::from("|`,[]{}"))], tokens("\"|`,[]{}\""));
+        assert_eq!(vec![symbol("a")], tokens("#| [|`,] |# a ; {}"));
+    }
+
+    #[test]
+    fn tokenize_boolean() {
+        assert_eq!(
+            vec!(token(Token::True, 0, 2), token(Token::False, 3, 2)),
+            Lexer::new("#t #f").tokenize().unwrap());
+        assert_eq!(
+            vec!(token(Token::LParen, 0, 1), token(Token::True, 1, 2), token(Token::RParen, 3, 1)),
+            Lexer::new("(#t)").tokenize().unwrap());
+        assert_eq!(
+            vec!(token(Token::True, 0, 1), token(Token::Nil, 2, 3), token(Token::Symbol(String::from("nil?")), 6, 4)),
+            Lexer::new("t nil nil?").tokenize().unwrap());
+        assert_eq!(
+            vec!(token(Token::Symbol(String::from("t")), 0, 1), token(Token::Symbol(String::from("nil")), 2, 3)),
+            Lexer::new("t nil").with_truthiness(Truthiness::Scheme).tokenize().unwrap());
+        assert!(Lexer::new("#true").tokenize().is_err());
+        assert!(Lexer::new("#ta").tokenize().is_err());
+    }
+
+    #[test]
+    fn tokenize_comment() {
+        let tokens = |s| Lexer::new(s).tokenize().unwrap().into_iter().map(|x| x.token).collect::<Vec<_>>();
+        let symbol = |s| Token::Symbol(String::from(s));
+        let list = vec![Token::LParen, symbol("a"), Token::RParen];
+
+        // Line comments
+        assert_eq!(list, tokens("(a) ; comment"));
+        assert_eq!(list, tokens("(a;comment\n)"));
+        assert_eq!(list, tokens("; comment\n(a);"));
+        assert_eq!(Vec::<Token>::new(), tokens(";"));
+
+        // Block comments
+        assert_eq!(list, tokens("#|comment|#(a)"));
+        assert_eq!(list, tokens("(#| comment |#a #|\n|#)"));
+        assert_eq!(list, tokens("(a) #| outer #| inner |# (b) |#"));
+        assert_eq!(list, tokens("(a)#||#"));
+
+        // Datum comments
+        assert_eq!(list, tokens("(a #;b)"));
+        assert_eq!(list, tokens("(#;(b (c)) a)"));
+        assert_eq!(list, tokens("(a #;'(b) #; \"c\")"));
+        assert_eq!(list, tokens("(#;#;b c a)"));
+        assert_eq!(list, tokens("(a #;(b ; )\n ))"));
+        assert_eq!(list, tokens("(a)#;b"));
+
+        let kind = |s| Lexer::new(s).tokenize().unwrap_err().kind;
+        assert_eq!(LexerErrorKind::UnterminatedComment, kind("(a #| b"));
+        assert_eq!(LexerErrorKind::UnterminatedComment, kind("(a #| #| b |#"));
+        assert_eq!(LexerErrorKind::Other, kind("(a #;)"));
+        assert_eq!(LexerErrorKind::UnterminatedComment, kind("(a) #;"));
+        assert_eq!(LexerErrorKind::UnterminatedComment, kind("#;(a"));
+        assert_eq!(LexerErrorKind::UnterminatedString, kind("#;\"a"));
+        assert_eq!(LexerErrorKind::Other, kind("#a"));
+
+        // Positions keep counting through comments
+        assert_eq!(
+            vec![token(Token::Integer(1), 6, 1)],
+            Lexer::new("#|x|# 1").tokenize().unwrap());
+        assert_eq!(Span::new(None, 2, 1, 4, 2), Lexer::new("; x\n#;").tokenize().unwrap_err().span);
+    }
+
+    #[test]
+    fn tokenize_position() {
+        let tokens = Lexer::new("(\u{3042}\n  x \"a\nb\" y)").tokenize().unwrap();
+        let positions: Vec<_> = tokens.iter().map(|x| (x.index, x.len, x.byte, x.line, x.column)).collect();
+        assert_eq!(
+            vec![(0, 1, 0, 1, 1), (1, 1, 1, 1, 2), (5, 1, 7, 2, 3), (7, 5, 9, 2, 5), (13, 1, 15, 3, 4), (14, 1, 16, 3, 5)],
+            positions);
+    }
+
+    #[test]
+    fn error_span() {
+        let span = |s| Lexer::new(s).tokenize().unwrap_err().span;
+        assert_eq!(Span::new(None, 1, 4, 3, 1), span("(a #)"));
+        assert_eq!(Span::new(None, 1, 3, 2, 4), span("x \"abc"));
+        assert_eq!(Span::new(None, 3, 1, 8, 6), span("(a\n \"b\"\n\"c\n  d"));
+        assert_eq!(Span::new(None, 1, 6, 5, 2), span("(a \"b\\qc\")"));
+        assert_eq!(Span::new(None, 1, 4, 3, 8), span("x \"\\u{d800}\""));
+
+        let file = Some(Rc::new(String::from("a.lisp")));
+        assert_eq!(
+            Span::new(file.clone(), 1, 1, 0, 1),
+            Lexer::with_file("#", file).tokenize().unwrap_err().span);
+    }
 }
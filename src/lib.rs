@@ -1,59 +1,156 @@
+pub mod bigint;
 pub mod lexer;
 pub mod parser;
 pub mod eval;
+pub mod number;
+pub mod span;
+pub mod symbol;
+pub mod truthiness;
 
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::rc::Rc;
-use lexer::{Lexer, LexerError};
-use parser::{Node, Parser};
+use lexer::{LexerError, LexerErrorKind};
+use parser::{Node, ParseError, ParseErrorKind};
 use eval::{Env, Eval, EvalError};
+use span::Span;
+use truthiness::Truthiness;
 
 #[derive(Debug)]
 pub enum LispError {
     Lexer(LexerError),
+    Parse(ParseError),
     Eval(EvalError),
+    Io(io::Error),
     EOF,
 }
 
+impl LispError {
+    pub fn span(&self) -> Option<&Span> {
+        match *self {
+            LispError::Lexer(ref err) => Some(&err.span),
+            LispError::Parse(ref err) => Some(&err.span),
+            LispError::Eval(ref err) => err.span.as_ref(),
+            LispError::Io(_) | LispError::EOF => None,
+        }
+    }
+
+    /// Whether the input ended inside a list, a string or a comment, so
+    /// that more input could complete it.
+    pub fn is_incomplete(&self) -> bool {
+        match *self {
+            LispError::Lexer(ref err) =>
+                err.kind == LexerErrorKind::UnterminatedString || err.kind == LexerErrorKind::UnterminatedComment,
+            LispError::Parse(ref err) => err.kind == ParseErrorKind::UnexpectedEOF,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for LispError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LispError::Lexer(ref err) => write!(f, "{}", err),
+            LispError::Parse(ref err) => write!(f, "{}", err),
+            LispError::Eval(ref err) => write!(f, "{}", err),
+            LispError::Io(ref err) => write!(f, "{}", err),
+            LispError::EOF => write!(f, "Nothing to evaluate"),
+        }
+    }
+}
+
 impl From<LexerError> for LispError {
     fn from(err: LexerError) -> Self {
         LispError::Lexer(err)
     }
 }
 
+impl From<ParseError> for LispError {
+    fn from(err: ParseError) -> Self {
+        LispError::Parse(err)
+    }
+}
+
 impl From<EvalError> for LispError {
     fn from(err: EvalError) -> Self {
         LispError::Eval(err)
     }
 }
 
+impl From<io::Error> for LispError {
+    fn from(err: io::Error) -> Self {
+        LispError::Io(err)
+    }
+}
+
 pub struct Lisp {
     eval: Eval,
     env: Env
 }
 
+impl Default for Lisp {
+    fn default() -> Self {
+        Lisp::new()
+    }
+}
+
 impl Lisp {
     pub fn new() -> Self {
+        Lisp::with_truthiness(Truthiness::default())
+    }
+
+    pub fn with_truthiness(truthiness: Truthiness) -> Self {
         Lisp {
-            eval: Eval::new(),
+            eval: Eval::with_truthiness(truthiness),
             env: Env::new(),
         }
     }
 
     pub fn eval_line(&mut self, line: &str) -> Result<Node, LispError> {
-        let tokens = try!(Lexer::new(line).tokenize());
-        match Parser::new(tokens).parse() {
-            Some(nodes) => Ok({
-                let nd : Rc<Node> = try!(self.eval.eval(&mut self.env, nodes));
-                (*nd.clone()).clone()
-            }),
-            None => Err(LispError::EOF),
+        self.eval_str(line)
+    }
+
+    /// Evaluates every top-level form in `s` in order, and returns the value
+    /// of the last one.
+    pub fn eval_str(&mut self, s: &str) -> Result<Node, LispError> {
+        let file = Rc::new(String::from("<input>"));
+        self.eval.add_source(file.clone(), s);
+        self.eval_source(file, s)
+    }
+
+    /// Relative paths given to `load` in the file are resolved against its
+    /// directory.
+    pub fn eval_file<P: AsRef<Path>>(&mut self, path: P) -> Result<Node, LispError> {
+        let source = fs::read_to_string(&path)?;
+        let file = Rc::new(path.as_ref().display().to_string());
+        self.eval.add_file(file.clone(), &source);
+        self.eval_source(file, &source)
+    }
+
+    /// The error message followed by the source line it points at, if any.
+    pub fn render_error(&self, err: &LispError) -> String {
+        let source = err.span().and_then(|x| x.file.as_ref()).and_then(|x| self.eval.source(x));
+        match (err.span(), source) {
+            (Some(span), Some(source)) => format!("{}\n{}", err, span.render(&source)),
+            _ => err.to_string(),
         }
     }
+
+    fn eval_source(&mut self, file: Rc<String>, s: &str) -> Result<Node, LispError> {
+        let result = self.eval.eval_source(&mut self.env, file, s)?;
+        result.map(|x| (*x).clone()).ok_or(LispError::EOF)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use eval::EvalErrorKind;
+    use lexer::Lexer;
+    use parser::Parser;
+    use symbol::Symbol;
 
     #[test]
     fn if_then_else() {
@@ -117,4 +214,837 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn closure() {
+        let mut env = Env::new();
+        for line in &[
+            "(setq make-adder (lambda (n) (lambda (x) (+ x n))))",
+            "(setq add2 (make-adder 2))",
+            "(setq n 100)"] {
+            let tokens = Lexer::new(line).tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            Eval::new().eval(&mut env, nodes).unwrap();
+        }
+        {
+            let tokens = Lexer::new("(add2 40)").tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            assert_eq!(
+                Node::Integer(42),
+                *Eval::new().eval(&mut env, nodes).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn closure_counter() {
+        let mut env = Env::new();
+        for line in &[
+            "(setq make-counter (lambda (n) (lambda (d) (if (= d 0) n (setq n (+ n d))))))",
+            "(setq counter (make-counter 40))",
+            "(setq n 0)",
+            "(counter 1)",
+            "(counter 1)"] {
+            let tokens = Lexer::new(line).tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            Eval::new().eval(&mut env, nodes).unwrap();
+        }
+        {
+            let tokens = Lexer::new("(counter 0)").tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            assert_eq!(
+                Node::Integer(42),
+                *Eval::new().eval(&mut env, nodes).unwrap()
+            );
+        }
+        {
+            let tokens = Lexer::new("((make-counter 7) 0)").tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            assert_eq!(
+                Node::Integer(7),
+                *Eval::new().eval(&mut env, nodes).unwrap()
+            );
+        }
+        assert_eq!(Some(Rc::new(Node::Integer(0))), env.get(Symbol::intern("n")));
+    }
+
+    #[test]
+    fn tail_call() {
+        let mut env = Env::new();
+        for line in &[
+            "(setq count (lambda (n acc) (if (<= n 0) acc (count (- n 1) (+ acc 2)))))",
+            "(setq even (lambda (n) (if (= n 0) (= 0 0) (odd (- n 1)))))",
+            "(setq odd (lambda (n) (if (= n 0) (= 0 1) (even (- n 1)))))"] {
+            let tokens = Lexer::new(line).tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            Eval::new().eval(&mut env, nodes).unwrap();
+        }
+        {
+            let tokens = Lexer::new("(count 100000 0)").tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            assert_eq!(
+                Node::Integer(200000),
+                *Eval::new().eval(&mut env, nodes).unwrap()
+            );
+        }
+        {
+            let tokens = Lexer::new("(even 10001)").tokenize().unwrap();
+            let nodes = Parser::new(tokens).parse().unwrap();
+            assert_eq!(
+                Node::False,
+                *Eval::new().eval(&mut env, nodes).unwrap()
+            );
+        }
+    }
+
+    fn eval(env: &mut Env, line: &str) -> Result<Rc<Node>, EvalError> {
+        let tokens = Lexer::new(line).tokenize().unwrap();
+        let nodes = Parser::new(tokens).parse().unwrap();
+        Eval::new().eval(env, nodes)
+    }
+
+    fn show(env: &mut Env, line: &str) -> String {
+        eval(env, line).unwrap().to_string()
+    }
+
+    #[test]
+    fn string() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq greeting (string-append \"Hello\" \", \" \"\u{4e16}\u{754c}\"))").unwrap();
+        assert_eq!(
+            Node::Str(String::from("Hello, \u{4e16}\u{754c}")),
+            *eval(&mut env, "greeting").unwrap());
+        assert_eq!(Node::Integer(9), *eval(&mut env, "(string-length greeting)").unwrap());
+        assert_eq!(
+            Node::Str(String::from("\u{4e16}\u{754c}")),
+            *eval(&mut env, "(substring greeting 7)").unwrap());
+        assert_eq!(
+            Node::Str(String::from("ell")),
+            *eval(&mut env, "(substring greeting 1 4)").unwrap());
+        assert!(eval(&mut env, "(substring greeting 3 10)").is_err());
+        assert!(eval(&mut env, "(substring greeting 3 2)").is_err());
+        assert_eq!(Node::Str(String::new()), *eval(&mut env, "(string-append)").unwrap());
+
+        assert_eq!(Node::True, *eval(&mut env, "(string= \"a\" \"a\" \"a\")").unwrap());
+        assert_eq!(Node::False, *eval(&mut env, "(string= \"a\" \"b\")").unwrap());
+        assert_eq!(Node::True, *eval(&mut env, "(string< \"a\" \"ab\" \"b\")").unwrap());
+        assert_eq!(Node::False, *eval(&mut env, "(string< \"b\" \"a\")").unwrap());
+        assert!(eval(&mut env, "(string< \"b\" 1)").is_err());
+
+        assert_eq!(Node::Integer(42), *eval(&mut env, "(string->number \"42\")").unwrap());
+        assert_eq!(Node::False, *eval(&mut env, "(string->number \"4 2\")").unwrap());
+        assert_eq!(Node::False, *eval(&mut env, "(string->number \"abc\")").unwrap());
+        for s in &["#;1 2", "42 ; x", " 42", "42\\n", "#|x|#1", "'1", "(1)", "", "-", "1e", "inf"] {
+            assert_eq!(Node::False, *eval(&mut env, &format!("(string->number {:?})", s)).unwrap(), "{}", s);
+        }
+        assert_eq!(Node::Integer(-7), *eval(&mut env, "(string->number \"-7\")").unwrap());
+        assert_eq!(
+            Node::Str(String::from("42")),
+            *eval(&mut env, "(number->string (+ 40 2))").unwrap());
+    }
+
+    #[test]
+    fn float() {
+        let mut env = Env::new();
+        assert_eq!(Node::Float(3.5), *eval(&mut env, "(+ 1 2.5)").unwrap());
+        assert_eq!(Node::Float(-0.5), *eval(&mut env, "(* -0.5 1)").unwrap());
+        assert_eq!(Node::Float(2.5), *eval(&mut env, "(/ 5 2.0)").unwrap());
+        assert_eq!(Node::Integer(2), *eval(&mut env, "(/ 5 2)").unwrap());
+        assert_eq!(Node::True, *eval(&mut env, "(= 1 1.0)").unwrap());
+        assert_eq!(Node::True, *eval(&mut env, "(< 1 1.5 2)").unwrap());
+        assert_eq!(Node::Float(3.0), *eval(&mut env, "(floor 3.7)").unwrap());
+        assert_eq!(Node::Float(4.0), *eval(&mut env, "(ceiling 3.2)").unwrap());
+        assert_eq!(Node::Float(4.0), *eval(&mut env, "(round 3.5)").unwrap());
+        assert_eq!(Node::Float(-3.0), *eval(&mut env, "(truncate -3.7)").unwrap());
+        assert_eq!(Node::Integer(7), *eval(&mut env, "(floor 7)").unwrap());
+        assert_eq!(Node::Integer(12), *eval(&mut env, "(sqrt 144)").unwrap());
+        assert_eq!(Node::Float(1.5), *eval(&mut env, "(sqrt 2.25)").unwrap());
+        assert_eq!(Node::Integer(1024), *eval(&mut env, "(expt 2 10)").unwrap());
+        assert_eq!(Node::Float(1.0), *eval(&mut env, "(exp 0)").unwrap());
+        assert_eq!(Node::Float(0.0), *eval(&mut env, "(log 1)").unwrap());
+        assert_eq!(Node::Float(0.0), *eval(&mut env, "(sin 0)").unwrap());
+        assert_eq!(Node::Float(1.0), *eval(&mut env, "(cos 0)").unwrap());
+        assert_eq!(Node::Float(0.0), *eval(&mut env, "(tan 0)").unwrap());
+        assert_eq!(Node::Float(3.0), *eval(&mut env, "(exact->inexact 3)").unwrap());
+        assert_eq!(Node::Integer(3), *eval(&mut env, "(inexact->exact 3.0)").unwrap());
+        assert!(eval(&mut env, "(inexact->exact 3.5)").is_err());
+        assert_eq!(Node::Float(1e-9), *eval(&mut env, "(string->number \"1e-9\")").unwrap());
+        assert_eq!(
+            Node::Str(String::from("0.5")),
+            *eval(&mut env, "(number->string (/ 1 2.0))").unwrap());
+    }
+
+    #[test]
+    fn negative_integer() {
+        let mut env = Env::new();
+        assert_eq!(Node::Integer(-1), *eval(&mut env, "(car '(-1 2))").unwrap());
+        assert_eq!(Node::Integer(2), *eval(&mut env, "(+ -5 +7)").unwrap());
+        assert_eq!(Node::Integer(-12), *eval(&mut env, "(- -5 7)").unwrap());
+    }
+
+    #[test]
+    fn bigint() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq fact (lambda (n acc) (if (<= n 1) acc (fact (- n 1) (* n acc)))))").unwrap();
+        assert_eq!(
+            Node::BigInt("30414093201713378043612608166064768844377641568960512000000000000".parse().unwrap()),
+            *eval(&mut env, "(fact 50 1)").unwrap());
+        assert_eq!(
+            Node::Integer(2432902008176640000),
+            *eval(&mut env, "(/ (fact 50 1) (/ (fact 50 1) (fact 20 1)))").unwrap());
+        assert_eq!(
+            Node::True,
+            *eval(&mut env, "(< 9223372036854775807 9223372036854775808 (fact 21 1))").unwrap());
+        assert_eq!(
+            Node::Integer(-9223372036854775807),
+            *eval(&mut env, "(+ -9223372036854775808 1)").unwrap());
+        assert_eq!(
+            Node::BigInt("-9223372036854775809".parse().unwrap()),
+            *eval(&mut env, "(- -9223372036854775808 1)").unwrap());
+        assert_eq!(
+            Node::Str(String::from("-123456789012345678901234567890")),
+            *eval(&mut env, "(number->string -123456789012345678901234567890)").unwrap());
+        assert_eq!(
+            Node::BigInt("123456789012345678901234567890".parse().unwrap()),
+            *eval(&mut env, "(string->number \"123456789012345678901234567890\")").unwrap());
+    }
+
+    #[test]
+    fn eval_error() {
+        let mut lisp = Lisp::new();
+        lisp.eval_line("(setq add (lambda (a b) (+ a b)))").unwrap();
+
+        let kind = |result| match result {
+            Err(LispError::Eval(err)) => err.kind,
+            other => panic!("Expected an EvalError, but got {:?}", other),
+        };
+        assert_eq!(
+            EvalErrorKind::ArityMismatch { expected: 2, got: 1 },
+            kind(lisp.eval_line("(add 1)")));
+        assert_eq!(
+            EvalErrorKind::UnboundVariable { name: Symbol::intern("x1") },
+            kind(lisp.eval_line("(+ x1 1)")));
+        assert_eq!(
+            EvalErrorKind::UnboundVariable { name: Symbol::intern("sub") },
+            kind(lisp.eval_line("(sub 2 1)")));
+        let mut scheme = Lisp::with_truthiness(Truthiness::Scheme);
+        assert_eq!(EvalErrorKind::EmptyApplication, kind(scheme.eval_line("()")));
+        assert_eq!(
+            EvalErrorKind::UnboundVariable { name: Symbol::intern("nil") },
+            kind(scheme.eval_line("nil")));
+        assert_eq!(EvalErrorKind::EmptyApplication, kind(scheme.eval_line("(+ 1 ())")));
+        assert_eq!(EvalErrorKind::DivisionByZero, kind(lisp.eval_line("(/ 1 0)")));
+        assert_eq!(EvalErrorKind::DivisionByZero, kind(lisp.eval_line("(/ 100000000000000000000 (- 1 1))")));
+        assert_eq!(EvalErrorKind::Overflow, kind(lisp.eval_line("(expt 10 100000000000)")));
+        assert_eq!(EvalErrorKind::Other, kind(lisp.eval_line("(+ 1 \"a\")")));
+
+        // Values in messages are printed the way they read
+        let message = |result| match result {
+            Err(LispError::Eval(err)) => err.message,
+            other => panic!("Expected an EvalError, but got {:?}", other),
+        };
+        assert_eq!("`car` takes only a list, but got (#t 1) 2", message(lisp.eval_line("(car (list #t 1) 2)")));
+        assert_eq!("`car` takes only a list, but got \"a\"", message(lisp.eval_line("(car \"a\")")));
+        assert_eq!("`not` takes only one argument, but got nothing", message(lisp.eval_line("(not)")));
+        assert_eq!("1 isn't a function", message(lisp.eval_line("(1 2)")));
+        assert_eq!("`length` is 3, which isn't a function", message(lisp.eval_line("(let ((length 3)) (length (list 1)))")));
+        assert_eq!("`x` is 1, which isn't a function", message(lisp.eval_line("(setq x 1) (x 2)")));
+        assert_eq!("(car (list 1)) is 1, which isn't a function", message(lisp.eval_line("((car (list 1)) 2)")));
+
+        // Builtins and special forms are suggested too
+        assert_eq!(
+            "Unknown function: \"lenght\", did you mean \"length\"?",
+            message(lisp.eval_line("(lenght '(1 2))")));
+        assert_eq!(
+            "Unknown function: \"lamda\", did you mean \"lambda\"?",
+            message(lisp.eval_line("(lamda (x) x)")));
+        assert_eq!("Unbound variable: \"cdr2\", did you mean \"cdr\"?", message(lisp.eval_line("cdr2")));
+
+        assert_eq!(Node::Float(f64::INFINITY), lisp.eval_line("(/ 1.0 0)").unwrap());
+        assert_eq!(Node::Integer(42), lisp.eval_line("(add 40 2)").unwrap());
+    }
+
+    #[test]
+    fn too_deep() {
+        // A debug build needs more than the stack a test thread gets by
+        // default to get as deep as the limit
+        let thread = ::std::thread::Builder::new().stack_size(32 << 20).spawn(|| {
+            let mut lisp = Lisp::new();
+            lisp.eval_line("(defun f (n) (if (= n 0) 0 (+ 1 (f (- n 1)))))").unwrap();
+            match lisp.eval_line("(f 10000)") {
+                Err(LispError::Eval(ref err)) if err.kind == EvalErrorKind::TooDeep => (),
+                other => panic!("Expected TooDeep, but got {:?}", other),
+            }
+            // The depth is back to where it was after the error
+            assert_eq!(Node::Integer(100), lisp.eval_line("(f 100)").unwrap());
+            assert_eq!(Node::Integer(100000), lisp.eval_line("(let loop ((n 0)) (if (< n 100000) (loop (+ n 1)) n))").unwrap());
+
+            let deep = format!("{}0{}", "(+ 1 ".repeat(100000), ")".repeat(100000));
+            match lisp.eval_line(&deep) {
+                Err(LispError::Parse(ref err)) if err.kind == ParseErrorKind::TooDeep => (),
+                other => panic!("Expected TooDeep, but got {:?}", other),
+            }
+        }).unwrap();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn env_after_error() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq f (lambda (n) (+ n (car n))))").unwrap();
+        assert!(eval(&mut env, "(f 1)").is_err());
+        assert_eq!(
+            EvalErrorKind::UnboundVariable { name: Symbol::intern("n") },
+            eval(&mut env, "n").unwrap_err().kind);
+
+        eval(&mut env, "(setq n 42)").unwrap();
+        assert!(eval(&mut env, "(f 1)").is_err());
+        assert_eq!(Node::Integer(42), *eval(&mut env, "n").unwrap());
+    }
+
+    #[test]
+    fn eval_str() {
+        let mut lisp = Lisp::new();
+        assert_eq!(Node::Integer(2), lisp.eval_str("(setq x 1) (+ x 1)").unwrap());
+        assert_eq!(Node::Integer(1), lisp.eval_line("x").unwrap());
+        assert_eq!(Node::Integer(3), lisp.eval_str("\n(setq y 2)\n\n(+ x y)\n").unwrap());
+        assert_eq!(Node::Integer(3), lisp.eval_str("; sum\n(+ x #| y |# 2) #;(car x)").unwrap());
+        assert_eq!(Node::Integer(6), lisp.eval_str("(setq *a.b->c?!* 5) (+ *a.b->c?!* 1)").unwrap());
+        match lisp.eval_str("  ") {
+            Err(LispError::EOF) => (),
+            other => panic!("Expected EOF, but got {:?}", other),
+        }
+        // Nothing runs if any form fails to parse
+        match lisp.eval_str("(setq x 100) (+ x 1))") {
+            Err(LispError::Parse(_)) => (),
+            other => panic!("Expected a ParseError, but got {:?}", other),
+        }
+        assert_eq!(Node::Integer(1), lisp.eval_line("x").unwrap());
+
+        // Input that more lines could complete, which the REPL waits for
+        let incomplete = |lisp: &mut Lisp, s| lisp.eval_str(s).unwrap_err().is_incomplete();
+        assert!(incomplete(&mut lisp, "(+ 1"));
+        assert!(incomplete(&mut lisp, "\"multi\n"));
+        assert!(incomplete(&mut lisp, "(a #| comment\n"));
+        assert!(incomplete(&mut lisp, "1 #;"));
+        assert!(!incomplete(&mut lisp, "(+ 1))"));
+        assert!(!incomplete(&mut lisp, "\"\\q\""));
+        assert!(!incomplete(&mut lisp, "(car 1)"));
+        assert_eq!(Node::Str(String::from("multi\nline")), lisp.eval_str("\"multi\nline\"").unwrap());
+    }
+
+    #[test]
+    fn eval_file() {
+        let dir = ::std::env::temp_dir();
+        let lib = dir.join(format!("tiny-rust-lisp-{}-lib.lisp", ::std::process::id()));
+        let main = dir.join(format!("tiny-rust-lisp-{}-main.lisp", ::std::process::id()));
+        fs::write(&lib, "(setq double (lambda (x) (* x 2)))\n(setq base 20)\n").unwrap();
+        fs::write(&main, format!("(load {:?})\n(double (+ base 1))\n", lib.to_str().unwrap())).unwrap();
+        let bad = dir.join(format!("tiny-rust-lisp-{}-bad.lisp", ::std::process::id()));
+        fs::write(&bad, "(setq loaded 1)\n(+ 1\n").unwrap();
+
+        let mut lisp = Lisp::new();
+        let result = lisp.eval_file(&main);
+        let missing = lisp.eval_file(dir.join("tiny-rust-lisp-missing.lisp"));
+        let load_missing = lisp.eval_line("(load \"tiny-rust-lisp-missing.lisp\")");
+        let load_bad = lisp.eval_line(&format!("(load {:?})", bad.to_str().unwrap()));
+        fs::remove_file(&bad).unwrap();
+        fs::remove_file(&lib).unwrap();
+        fs::remove_file(&main).unwrap();
+
+        assert_eq!(Node::Integer(42), result.unwrap());
+        assert_eq!(Node::Integer(20), lisp.eval_line("base").unwrap());
+        match missing {
+            Err(LispError::Io(_)) => (),
+            other => panic!("Expected an I/O error, but got {:?}", other),
+        }
+        assert!(load_missing.is_err());
+        // Like `eval_file`, nothing in a file runs if any of it fails to parse
+        assert!(load_bad.is_err());
+        assert!(lisp.eval_line("loaded").is_err());
+    }
+
+    #[test]
+    fn load_relative() {
+        let dir = ::std::env::temp_dir().join(format!("tiny-rust-lisp-{}-load", ::std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.lisp"), "(load \"lib/util.lisp\")\n(twice 21)\n").unwrap();
+        fs::write(dir.join("lib/util.lisp"), "(load \"helper.lisp\")\n(defun twice (x) (double x))\n").unwrap();
+        fs::write(dir.join("lib/helper.lisp"), "(defun double (x) (* x 2))\n").unwrap();
+
+        // Paths are resolved against the file that loads them, not the
+        // current directory
+        let mut lisp = Lisp::new();
+        let result = lisp.eval_file(dir.join("main.lisp"));
+        let from_input = lisp.eval_line("(load \"lib/helper.lisp\")");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(Node::Integer(42), result.unwrap());
+        assert!(from_input.is_err());
+    }
+
+    #[test]
+    fn render_error() {
+        let mut lisp = Lisp::new();
+        lisp.eval_str("(setq f (lambda (x)\n  (+ x \"a\")))").unwrap();
+        let render = |lisp: &mut Lisp, s| {
+            let err = lisp.eval_str(s).unwrap_err();
+            lisp.render_error(&err)
+        };
+
+        // Errors inside a function point into the line it was defined on
+        assert_eq!(
+            "`+` takes only numbers, but got \"a\"\n --> <input>:2:8\n  |\n2 |   (+ x \"a\")))\n  |        ^^^",
+            render(&mut lisp, "(f 1)"));
+        assert_eq!(
+            "Unknown function: \"foo\"\n --> <input>:1:2\n  |\n1 | (foo 1)\n  |  ^^^",
+            render(&mut lisp, "(foo 1)"));
+        assert_eq!(
+            "Unbound variable: \"x1\", did you mean \"x\"?\n --> <input>:2:4\n  |\n2 | (+ x1 1)\n  |    ^^",
+            render(&mut lisp, "(setq x 1)\n(+ x1 1)"));
+        assert_eq!(
+            "Unknown function: \"ff\", did you mean \"f\"?\n --> <input>:1:2\n  |\n1 | (ff 1)\n  |  ^^",
+            render(&mut lisp, "(ff 1)"));
+        assert_eq!(
+            "Division by zero: 1 / 0\n --> <input>:2:1\n  |\n2 | (/ 1 0)\n  | ^^^^^^^",
+            render(&mut lisp, "1\n(/ 1 0)"));
+        assert_eq!(
+            "Unexpected `)`\n --> <input>:1:8\n  |\n1 | (+ 1 2))\n  |        ^",
+            render(&mut lisp, "(+ 1 2))"));
+        assert_eq!(
+            "Unexpected charactor: [#]\n --> <input>:1:4\n  |\n1 | (a #)\n  |    ^",
+            render(&mut lisp, "(a #)"));
+
+        let err = lisp.eval_line("(f 1)").unwrap_err();
+        assert_eq!(Some(Span::new(Some(Rc::new(String::from("<input>"))), 2, 8, 27, 3)), err.span().cloned());
+        assert_eq!("Nothing to evaluate", lisp.render_error(&LispError::EOF));
+    }
+
+    #[test]
+    fn keyword() {
+        let mut env = Env::new();
+        let mode = Node::Keyword(Symbol::intern("mode"));
+        assert_eq!(mode, *eval(&mut env, ":mode").unwrap());
+        assert_eq!(mode, *eval(&mut env, "':mode").unwrap());
+        assert_eq!(mode, *eval(&mut env, "(car '(:mode :append))").unwrap());
+        eval(&mut env, "(setq f (lambda (x) (if (= 1 1) x)))").unwrap();
+        assert_eq!(mode, *eval(&mut env, "(f :mode)").unwrap());
+        assert!(eval(&mut env, "(setq :mode 1)").is_err());
+        assert!(eval(&mut env, "(:mode 1)").is_err());
+        assert_ne!(mode, Node::Symbol(Symbol::intern("mode")));
+    }
+
+    #[test]
+    fn quote() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq x 42)").unwrap();
+        assert_eq!(Node::Symbol(Symbol::intern("x")), *eval(&mut env, "'x").unwrap());
+        assert_eq!(Node::Symbol(Symbol::intern("x")), *eval(&mut env, "(quote x)").unwrap());
+        assert_eq!(Node::Integer(42), *eval(&mut env, "'42").unwrap());
+        assert_eq!(Node::Str(String::from("s")), *eval(&mut env, "'\"s\"").unwrap());
+        assert_eq!(
+            *Node::list(vec![
+                Rc::new(Node::Symbol(Symbol::intern("quote"))),
+                Node::list(vec![Rc::new(Node::Integer(1)), Rc::new(Node::Integer(2))]),
+            ]),
+            *eval(&mut env, "''(1 2)").unwrap());
+        assert_eq!(Node::Nil, *eval(&mut env, "'()").unwrap());
+        assert_eq!(Node::Symbol(Symbol::intern("x")), *eval(&mut env, "(car '(x y))").unwrap());
+        assert_eq!(Node::Integer(1), *eval(&mut env, "(car (car '((1 2) 3)))").unwrap());
+        assert_eq!(Node::Symbol(Symbol::intern("quote")), *eval(&mut env, "(car ''x)").unwrap());
+        assert!(eval(&mut env, "(quote 1 2)").is_err());
+    }
+
+    #[test]
+    fn list() {
+        let mut env = Env::new();
+        assert_eq!("(1 . 2)", show(&mut env, "(cons 1 2)"));
+        assert_eq!("(1 2 3)", show(&mut env, "(cons 1 '(2 3))"));
+        assert_eq!("(1 2 . 3)", show(&mut env, "'(1 . (2 . 3))"));
+        assert_eq!("(3 (4))", show(&mut env, "(list (+ 1 2) (list 4))"));
+        assert_eq!("()", show(&mut env, "(list)"));
+        assert_eq!("b", show(&mut env, "(cdr '(a . b))"));
+        assert_eq!("()", show(&mut env, "(car '())"));
+
+        eval(&mut env, "(setq xs '(1 2 3))").unwrap();
+        assert_eq!("#t", show(&mut env, "(null? '())"));
+        assert_eq!("#f", show(&mut env, "(null? xs)"));
+        assert_eq!("#t", show(&mut env, "(pair? xs)"));
+        assert_eq!("#f", show(&mut env, "(pair? '())"));
+        assert_eq!("3", show(&mut env, "(length xs)"));
+        assert_eq!("0", show(&mut env, "(length '())"));
+        assert!(eval(&mut env, "(length '(1 . 2))").is_err());
+        assert_eq!("(1 2 3 4 5)", show(&mut env, "(append xs '(4) '() '(5))"));
+        assert_eq!("(1 2 3 . 4)", show(&mut env, "(append xs 4)"));
+        assert_eq!("()", show(&mut env, "(append)"));
+        assert!(eval(&mut env, "(append 1 xs)").is_err());
+        assert_eq!("(3 2 1)", show(&mut env, "(reverse xs)"));
+        assert_eq!("3", show(&mut env, "(list-ref xs 2)"));
+        assert!(eval(&mut env, "(list-ref xs 3)").is_err());
+        assert!(eval(&mut env, "(list-ref xs -1)").is_err());
+        assert_eq!("2", show(&mut env, "(nth 1 xs)"));
+        assert_eq!("()", show(&mut env, "(nth 3 xs)"));
+        assert_eq!("(3)", show(&mut env, "(last xs)"));
+        assert_eq!("(2 . 3)", show(&mut env, "(last '(1 2 . 3))"));
+        assert_eq!("()", show(&mut env, "(last '())"));
+        assert!(eval(&mut env, "(car 1)").is_err());
+        assert!(eval(&mut env, "(cons 1)").is_err());
+
+        // The tail is shared rather than copied
+        let xs = eval(&mut env, "xs").unwrap();
+        let ys = eval(&mut env, "(cons 0 xs)").unwrap();
+        if let Node::Pair(_, ref tl) = *ys {
+            assert!(Rc::ptr_eq(&xs, tl));
+        }
+        else {
+            panic!("{:?} isn't a pair", ys);
+        }
+        let zs = eval(&mut env, "(append '(0) xs)").unwrap();
+        assert_eq!(*ys, *zs);
+
+        // Long lists can be built and dropped without running out of stack
+        eval(&mut env, "(setq range (lambda (n acc) (if (<= n 0) acc (range (- n 1) (cons n acc)))))").unwrap();
+        assert_eq!("100000", show(&mut env, "(length (range 100000 '()))"));
+        assert_eq!("100000", show(&mut env, "(car (last (range 100000 '())))"));
+
+        // Or compared and formatted, even into an error message
+        eval(&mut env, "(setq long (range 100000 '()))").unwrap();
+        assert_eq!(eval(&mut env, "long").unwrap(), eval(&mut env, "(range 100000 '())").unwrap());
+        assert_ne!(eval(&mut env, "long").unwrap(), eval(&mut env, "(range 99999 '())").unwrap());
+        let debug = format!("{:?}", eval(&mut env, "long").unwrap());
+        assert!(debug.starts_with("Pair(Integer(1), Pair(Integer(2), "));
+        assert!(debug.ends_with(&format!("Pair(Integer(100000), Nil{}", ")".repeat(100000))));
+        let err = eval(&mut env, "(+ 1 long)").unwrap_err();
+        assert!(err.message.starts_with("`+` takes only numbers, but got (1 2 3 "), "{}", &err.message[..100]);
+        assert!(err.message.ends_with(" 99999 100000)"));
+
+        // The same goes for lists nested in their car
+        assert_eq!("built", show(&mut env, "(let loop ((i 0) (acc '())) (if (< i 200000) (loop (+ i 1) (list acc)) 'built))"));
+        eval(&mut env, "(setq nest (lambda (n acc) (if (<= n 0) acc (nest (- n 1) (list acc)))))").unwrap();
+        eval(&mut env, "(setq deep (nest 100000 1))").unwrap();
+        assert_eq!(eval(&mut env, "deep").unwrap(), eval(&mut env, "(nest 100000 1)").unwrap());
+        assert_ne!(eval(&mut env, "deep").unwrap(), eval(&mut env, "(nest 100000 2)").unwrap());
+        assert_eq!(format!("{}1{}", "(".repeat(100000), ")".repeat(100000)), show(&mut env, "deep"));
+        assert_eq!(
+            format!("{}Integer(1), Nil){}", "Pair(".repeat(100000), ", Nil)".repeat(99999)),
+            format!("{:?}", eval(&mut env, "deep").unwrap()));
+        assert_eq!("(1 (2 (3)) . 4)", show(&mut env, "(cons 1 (cons (list 2 (list 3)) 4))"));
+    }
+
+    #[test]
+    fn quoted_list_in_variable() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq xs '(1 2 3))").unwrap();
+        assert_eq!(Node::Integer(1), *eval(&mut env, "(car xs)").unwrap());
+        assert_eq!("(2 3)", eval(&mut env, "(cdr xs)").unwrap().to_string());
+        assert_eq!("(1 2 3)", eval(&mut env, "xs").unwrap().to_string());
+
+        // Values aren't evaluated again when they are looked up
+        eval(&mut env, "(setq form '(+ 1 2) name 'y y 1)").unwrap();
+        assert_eq!("(+ 1 2)", eval(&mut env, "form").unwrap().to_string());
+        assert_eq!(Node::Symbol(Symbol::intern("y")), *eval(&mut env, "name").unwrap());
+        eval(&mut env, "(setq id (lambda (x) (if (= 1 1) x)))").unwrap();
+        assert_eq!("(+ 1 2)", eval(&mut env, "(id form)").unwrap().to_string());
+        assert_eq!("(1 2 3)", eval(&mut env, "(id (id xs))").unwrap().to_string());
+
+        assert_eq!(Node::Nil, *eval(&mut env, "(if (= 1 2) 1)").unwrap());
+        assert!(eval(&mut env, "(+ 1 . 2)").is_err());
+    }
+
+    #[test]
+    fn print() {
+        let mut env = Env::new();
+        // Printing and reading back gives the same datum
+        for s in &["42", "-7", "123456789012345678901234567890", "2.5", "1e100", "-0.0",
+                   "+inf.0", "-inf.0", "+nan.0", "\"a \\\"b\\\" \\\\ \\n\\t\\u{7}\u{3042}\"", "x", ":mode",
+                   "#t", "#f", "()", "(1 (2 \"s\") . x)", "(quote x)", "((a . b) (c))"] {
+            let node = eval(&mut env, &format!("'{}", s)).unwrap();
+            assert_eq!(*s, node.to_string());
+            let again = eval(&mut env, &format!("'{}", node)).unwrap();
+            assert_eq!(node.to_string(), again.to_string());
+            if *s != "+nan.0" {
+                assert_eq!(node, again);
+            }
+        }
+        assert_eq!("0.5", eval(&mut env, "'.5").unwrap().to_string());
+        assert_eq!("(1 2)", eval(&mut env, "'(1 . (2))").unwrap().to_string());
+        assert_eq!("+inf.0", eval(&mut env, "(number->string (/ 1.0 0))").unwrap().display().to_string());
+        assert_eq!("#<lambda (n x)>", eval(&mut env, "(lambda (n x) (+ n x))").unwrap().to_string());
+
+        assert_eq!("\"a\\nb\"", eval(&mut env, "\"a\\nb\"").unwrap().to_string());
+        assert_eq!("a\nb", eval(&mut env, "\"a\\nb\"").unwrap().display().to_string());
+        assert_eq!("(a \"b\")", eval(&mut env, "'(a \"b\")").unwrap().to_string());
+        assert_eq!("(a b)", eval(&mut env, "'(a \"b\")").unwrap().display().to_string());
+        assert_eq!("42", eval(&mut env, "42").unwrap().display().to_string());
+    }
+
+    #[test]
+    fn let_forms() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq x 1)").unwrap();
+        assert_eq!("3", show(&mut env, "(let ((x 2) (y x)) (+ x y))"));
+        assert_eq!("4", show(&mut env, "(let* ((x 2) (y x)) (+ x y))"));
+        assert_eq!("5", show(&mut env, "(let () 5)"));
+        assert_eq!("20", show(&mut env, "(let ((x 10)) (let ((y (* x 2))) y))"));
+        assert_eq!("#t", show(&mut env, "(letrec ((ev? (lambda (n) (if (= n 0) #t (od? (- n 1)))))
+                                                 (od? (lambda (n) (if (= n 0) #f (ev? (- n 1))))))
+                                           (ev? 1000))"));
+        assert_eq!("12", show(&mut env, "((let ((n 5)) (lambda (m) (+ n m))) 7)"));
+
+        // Named `let` loops in constant stack space
+        assert_eq!("5000050000", show(&mut env, "(let loop ((i 0) (acc 0)) (if (> i 100000) acc (loop (+ i 1) (+ acc i))))"));
+
+        // Functions from `letrec` and named `let` still work once they've
+        // left the scope
+        eval(&mut env, "(define ev (letrec ((ev? (lambda (n) (if (= n 0) #t (od? (- n 1)))))
+                                            (od? (lambda (n) (if (= n 0) #f (ev? (- n 1))))))
+                                      ev?))").unwrap();
+        assert_eq!("#f", show(&mut env, "(ev 7)"));
+        eval(&mut env, "(define down (let loop ((n 0)) (if (= n 0) loop (if (= n 1) 'done (loop (- n 1))))))").unwrap();
+        assert_eq!("done", show(&mut env, "(down 5)"));
+
+        // Nothing leaks out of the scope, and globals are left alone
+        eval(&mut env, "(let ((x 1)) (setq x 10 z 2))").unwrap();
+        assert_eq!("1", show(&mut env, "x"));
+        for name in &["y", "z", "ev?", "od?", "loop"] {
+            assert_eq!(
+                EvalErrorKind::UnboundVariable { name: Symbol::intern(name) },
+                eval(&mut env, name).unwrap_err().kind);
+        }
+
+        assert!(eval(&mut env, "(let ((1 2)) 1)").is_err());
+        assert!(eval(&mut env, "(let (x) x)").is_err());
+        assert!(eval(&mut env, "(let ((x 1)))").is_err());
+        assert!(eval(&mut env, "(let* ((x 1) (y z)) y)").is_err());
+        assert!(eval(&mut env, "(letrec ((a b) (b 1)) a)").is_err());
+        assert!(eval(&mut env, "(let loop ((i 0)) (loop))").is_err());
+    }
+
+    #[test]
+    fn progn() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq calls 0)").unwrap();
+        assert_eq!("3", show(&mut env, "(progn 1 2 3)"));
+        assert_eq!("3", show(&mut env, "(begin (setq calls (+ calls 1)) (+ 1 2))"));
+        assert_eq!("()", show(&mut env, "(progn)"));
+        assert_eq!("1", show(&mut env, "calls"));
+        assert!(eval(&mut env, "(progn (car 1) 2)").is_err());
+
+        // Every form of a body runs, and the last one gives the value
+        eval(&mut env, "(setq f (lambda (x) (setq calls (+ calls 1)) (* x 2)))").unwrap();
+        assert_eq!("6", show(&mut env, "(f 3)"));
+        assert_eq!("2", show(&mut env, "calls"));
+        assert_eq!("#<lambda (x)>", show(&mut env, "(lambda (x) x)"));
+        assert_eq!("5", show(&mut env, "((lambda (x) x) 5)"));
+        assert_eq!("7", show(&mut env, "(let ((x 3)) (setq calls x) (+ x 4))"));
+        assert_eq!("3", show(&mut env, "calls"));
+        assert_eq!("4", show(&mut env, "(let* ((x 1)) x (+ x 3))"));
+        assert_eq!("2", show(&mut env, "(letrec ((x 1)) 0 (+ x 1))"));
+
+        // The last form is in tail position
+        assert_eq!("100000", show(&mut env, "(let loop ((i 0)) (setq calls i) (if (< i 100000) (loop (+ i 1)) i))"));
+        assert_eq!("100000", show(&mut env, "calls"));
+        assert!(eval(&mut env, "(lambda (x))").is_err());
+    }
+
+    #[test]
+    fn defun() {
+        let mut env = Env::new();
+        assert_eq!("fib", show(&mut env, "(defun fib (n) \"The nth Fibonacci number.\" (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))"));
+        assert_eq!("55", show(&mut env, "(fib 10)"));
+        assert_eq!("#<lambda fib (n)>", show(&mut env, "fib"));
+        assert_eq!("\"The nth Fibonacci number.\"", show(&mut env, "(documentation fib)"));
+
+        assert_eq!("square", show(&mut env, "(define (square x) (* x x))"));
+        assert_eq!("81", show(&mut env, "(square (square 3))"));
+        assert_eq!("()", show(&mut env, "(documentation square)"));
+        assert_eq!("answer", show(&mut env, "(define answer (square 7))"));
+        assert_eq!("49", show(&mut env, "answer"));
+
+        // A lone string is the body rather than the documentation
+        eval(&mut env, "(defun greeting () \"hello\")").unwrap();
+        assert_eq!("\"hello\"", show(&mut env, "(greeting)"));
+        assert_eq!("()", show(&mut env, "(documentation greeting)"));
+        assert_eq!("\"Adds one.\"", show(&mut env, "(documentation (lambda (x) \"Adds one.\" (+ x 1)))"));
+
+        // Definitions inside a body are local to it
+        assert_eq!("6", show(&mut env, "(let () (defun inner (x) (* x 2)) (inner 3))"));
+        assert!(eval(&mut env, "inner").is_err());
+
+        let err = eval(&mut env, "(fib 1 2)").unwrap_err();
+        assert_eq!(EvalErrorKind::ArityMismatch { expected: 1, got: 2 }, err.kind);
+        assert_eq!("`fib` takes 1 argument (n), but got 1 2", err.message);
+
+        assert!(eval(&mut env, "(defun f)").is_err());
+        assert!(eval(&mut env, "(defun f (1) 1)").is_err());
+        assert!(eval(&mut env, "(defun \"f\" () 1)").is_err());
+        assert!(eval(&mut env, "(define (1 x) x)").is_err());
+        assert!(eval(&mut env, "(define x 1 2)").is_err());
+        assert!(eval(&mut env, "(documentation 1)").is_err());
+
+        // Definitions take the place of builtins of the same name
+        let mut env = Env::new();
+        assert_eq!("length", show(&mut env, "(defun length (x) 42)"));
+        assert_eq!("42", show(&mut env, "(length '(1))"));
+        eval(&mut env, "(define (last xs) 'mine)").unwrap();
+        assert_eq!("mine", show(&mut env, "(last '(1 2))"));
+        eval(&mut env, "(setq list (lambda (x) (cons x x)))").unwrap();
+        assert_eq!("(1 . 1)", show(&mut env, "(list 1)"));
+        assert_eq!("3", show(&mut env, "(let ((car (lambda (x) 3))) (car '(1 . 2)))"));
+        assert_eq!("1", show(&mut env, "(car '(1 . 3))"));
+    }
+
+    #[test]
+    fn trace() {
+        let mut lisp = Lisp::new();
+        lisp.eval_str("(defun f (x) (car x))
+                       (defun g (x) (+ 1 (f x)))
+                       (defun h (x) (g x))
+                       (defun k (x) (f x) x)
+                       (setq anon (lambda (x) (g x)))
+                       (defun down (n) (if (= n 0) (car n) (+ 1 (down (- n 1)))))").unwrap();
+        let trace = |lisp: &mut Lisp, s| match lisp.eval_str(s) {
+            Err(LispError::Eval(err)) => err.trace.iter().map(|x| x.name()).collect::<Vec<_>>(),
+            other => panic!("Expected an EvalError, but got {:?}", other),
+        };
+
+        assert_eq!(vec!["f"], trace(&mut lisp, "(f 1)"));
+        assert_eq!(vec!["f", "g"], trace(&mut lisp, "(g 1)"));
+        assert_eq!(vec!["f", "k"], trace(&mut lisp, "(k 1)"));
+        // `h` calls `g` in tail position, so `g` doesn't return to it
+        assert_eq!(vec!["f", "g"], trace(&mut lisp, "(h 1)"));
+        assert_eq!(vec!["f", "g"], trace(&mut lisp, "(anon 1)"));
+        // The arguments are evaluated before `g` is called
+        assert_eq!(vec!["f"], trace(&mut lisp, "(g (f 1))"));
+        assert!(trace(&mut lisp, "(car 1)").is_empty());
+        assert!(trace(&mut lisp, "(f)").is_empty());
+
+        assert_eq!(
+            "`car` takes only a list, but got 1\n    in `f`\n    in `g`",
+            lisp.eval_str("(g 1)").unwrap_err().to_string());
+        assert_eq!(
+            "`car` takes only a list, but got 0\n    in `down` (4 times)",
+            lisp.eval_str("(down 3)").unwrap_err().to_string());
+    }
+
+    #[test]
+    fn conditionals() {
+        let mut env = Env::new();
+        eval(&mut env, "(setq hits 0)").unwrap();
+        eval(&mut env, "(defun hit (x) (setq hits (+ hits 1)) x)").unwrap();
+
+        assert_eq!("#t", show(&mut env, "(and)"));
+        assert_eq!("3", show(&mut env, "(and (= 1 1) (< 1 2) 3)"));
+        assert_eq!("#f", show(&mut env, "(or)"));
+        assert_eq!("#t", show(&mut env, "(or (= 1 2) (= 1 1))"));
+        assert_eq!("#f", show(&mut env, "(not (= 1 1))"));
+        assert_eq!("#t", show(&mut env, "(not #f)"));
+
+        // Operands after the deciding one aren't evaluated
+        assert_eq!("#f", show(&mut env, "(and #f (hit #t) (car 1))"));
+        assert_eq!("#t", show(&mut env, "(or #t (hit #t) (car 1))"));
+        assert_eq!("()", show(&mut env, "(when (= 1 2) (hit 1) (car 1))"));
+        assert_eq!("()", show(&mut env, "(unless (= 1 1) (hit 1) (car 1))"));
+        assert_eq!("2", show(&mut env, "(cond ((= 1 2) (car 1)) ((= 1 1) 2) ((hit #t) (car 1)))"));
+        assert_eq!("0", show(&mut env, "hits"));
+        assert!(eval(&mut env, "(and #t (car 1))").is_err());
+        assert!(eval(&mut env, "(or #f (car 1))").is_err());
+
+        assert_eq!("2", show(&mut env, "(when (= 1 1) (hit 1) 2)"));
+        assert_eq!("2", show(&mut env, "(unless (= 1 2) (hit 1) 2)"));
+        assert_eq!("2", show(&mut env, "hits"));
+        assert_eq!("other", show(&mut env, "(cond ((= 1 2) 'one) (else 'other))"));
+        assert_eq!("#t", show(&mut env, "(cond ((= 1 2) 'one) ((= 2 2)))"));
+        assert_eq!("()", show(&mut env, "(cond ((= 1 2) 'one))"));
+        assert_eq!("1", show(&mut env, "(cond (1))"));
+
+        assert_eq!("composite", show(&mut env, "(case (* 2 3) ((2 3 5 7) 'prime) ((1 4 6 8 9) 'composite))"));
+        assert_eq!("keyword", show(&mut env, "(case :b ((:a :b) 'keyword) (else 'other))"));
+        assert_eq!("list", show(&mut env, "(case '(1 2) (((1 2)) 'list) (otherwise 'other))"));
+        assert_eq!("other", show(&mut env, "(case \"x\" ((x) 'symbol) (else 'other))"));
+        assert_eq!("()", show(&mut env, "(case 10 ((1) 'one))"));
+        assert_eq!("3", show(&mut env, "(case (hit 1) ((1) 'one (hit 3)) ((1) (car 1)))"));
+        assert_eq!("4", show(&mut env, "hits"));
+
+        // The last operand of `and` and `or` and the bodies are in tail position
+        eval(&mut env, "(defun count-down (n) (or (= n 0) (count-down (- n 1))))").unwrap();
+        assert_eq!("#t", show(&mut env, "(count-down 100000)"));
+        eval(&mut env, "(defun count-up (n) (cond ((< n 100000) (count-up (+ n 1))) (else n)))").unwrap();
+        assert_eq!("100000", show(&mut env, "(count-up 0)"));
+
+        assert!(eval(&mut env, "(not 1 2)").is_err());
+        assert!(eval(&mut env, "(cond ())").is_err());
+        assert!(eval(&mut env, "(case 1 (1 'one))").is_err());
+        assert!(eval(&mut env, "(when)").is_err());
+    }
+
+    #[test]
+    fn truthiness() {
+        let show = |lisp: &mut Lisp, s| lisp.eval_str(s).unwrap().to_string();
+
+        // `()` is `nil` and false, like in Common Lisp
+        let mut lisp = Lisp::new();
+        assert_eq!("()", show(&mut lisp, "nil"));
+        assert_eq!("()", show(&mut lisp, "()"));
+        assert_eq!("#t", show(&mut lisp, "t"));
+        assert_eq!("#t", show(&mut lisp, "(null? nil)"));
+        assert_eq!("#t", show(&mut lisp, "(null? (= 1 2))"));
+        assert_eq!("()", show(&mut lisp, "(car (= 1 2))"));
+        assert_eq!("()", show(&mut lisp, "(cdr #f)"));
+        assert_eq!("0", show(&mut lisp, "(length #f)"));
+        assert_eq!("(1 2)", show(&mut lisp, "(append '(1) #f '(2))"));
+        assert_eq!("(1)", show(&mut lisp, "(append '(1) #f)"));
+        assert_eq!("()", show(&mut lisp, "(reverse #f)"));
+        assert_eq!("()", show(&mut lisp, "(nth 0 #f)"));
+        assert_eq!("()", show(&mut lisp, "(last #f)"));
+        assert!(lisp.eval_str("(list-ref #f 0)").unwrap_err().to_string().contains("out of bounds"));
+        assert_eq!("#f", show(&mut lisp, "(null? 0)"));
+        assert_eq!(Node::Nil, lisp.eval_str("'()").unwrap());
+        assert_eq!(Node::Nil, lisp.eval_str("(car '(nil))").unwrap());
+        assert_eq!(Node::Nil, lisp.eval_str("(if #f 1)").unwrap());
+        assert_eq!(Node::Nil, lisp.eval_str("(cdr '(1))").unwrap());
+        assert_eq!("2", show(&mut lisp, "(if '() 1 2)"));
+        assert_eq!("2", show(&mut lisp, "(if nil 1 2)"));
+        assert_eq!("2", show(&mut lisp, "(if #f 1 2)"));
+        assert_eq!("1", show(&mut lisp, "(if 0 1 2)"));
+        assert_eq!("1", show(&mut lisp, "(if \"\" 1 2)"));
+        assert_eq!("last", show(&mut lisp, "(if (cdr '(1)) 'more 'last)"));
+        assert_eq!("2", show(&mut lisp, "(and 1 2)"));
+        assert_eq!("()", show(&mut lisp, "(and 1 nil 2)"));
+        assert_eq!("3", show(&mut lisp, "(or nil #f 3)"));
+        assert_eq!("()", show(&mut lisp, "(or #f nil)"));
+        assert_eq!("#t", show(&mut lisp, "(not nil)"));
+        assert_eq!("#f", show(&mut lisp, "(not 0)"));
+        assert_eq!("2", show(&mut lisp, "(cond (nil 1) (t 2))"));
+        assert_eq!("(1)", show(&mut lisp, "(cond ((cdr '(0 1))))"));
+        assert_eq!("()", show(&mut lisp, "(when '() 1)"));
+        assert_eq!("1", show(&mut lisp, "(unless nil 1)"));
+        assert_eq!("0", show(&mut lisp, "(let loop ((xs '(1 2 3)) (n 0)) (if (pair? xs) (loop (cdr xs) (+ n 1)) (- n 3)))"));
+
+        // Only `#f` is false, like in Scheme
+        let mut lisp = Lisp::with_truthiness(Truthiness::Scheme);
+        assert_eq!("1", show(&mut lisp, "(if '() 1 2)"));
+        assert_eq!("2", show(&mut lisp, "(if #f 1 2)"));
+        assert_eq!("1", show(&mut lisp, "(if 0 1 2)"));
+        assert_eq!("#f", show(&mut lisp, "(not '())"));
+        assert_eq!("()", show(&mut lisp, "(or '() 3)"));
+        assert_eq!("3", show(&mut lisp, "(and '() 3)"));
+        assert_eq!("#f", show(&mut lisp, "(and #f 3)"));
+        assert_eq!("1", show(&mut lisp, "(when '() 1)"));
+        assert!(lisp.eval_str("()").is_err());
+        assert!(lisp.eval_str("nil").is_err());
+        assert_eq!("#f", show(&mut lisp, "(null? (= 1 2))"));
+        assert!(lisp.eval_str("(car #f)").is_err());
+        assert!(lisp.eval_str("(length #f)").is_err());
+        assert_eq!("1", show(&mut lisp, "(let ((t 1)) t)"));
+        lisp.eval_str("(define t 5)").unwrap();
+        assert_eq!("5", show(&mut lisp, "t"));
+        assert_eq!("(t nil)", show(&mut lisp, "'(t nil)"));
+        assert_eq!("0", show(&mut lisp, "(let loop ((xs '(1 2 3)) (n 0)) (if (pair? xs) (loop (cdr xs) (+ n 1)) (- n 3)))"));
+
+        // `t` and `nil` aren't symbols in Common Lisp, so they can't be bound
+        let mut lisp = Lisp::new();
+        assert!(lisp.eval_str("(setq t 1)").is_err());
+        assert!(lisp.eval_str("(lambda (nil) 1)").is_err());
+    }
 }
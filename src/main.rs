@@ -1,20 +1,35 @@
 extern crate tiny_rust_lisp;
 
+use std::env;
 use std::io::{self, Write};
+use std::process;
 use tiny_rust_lisp::{Lisp, LispError};
 
 fn main() {
     let mut lisp = Lisp::new();
-    
+
+    for path in env::args().skip(1) {
+        if let Err(err) = lisp.eval_file(&path) {
+            println!("Failed to load {}: {}", path, lisp.render_error(&err));
+            process::exit(1);
+        }
+    }
+
+    let mut input = String::new();
     loop {
-        print!("> ");
+        print!("{}", if input.is_empty() { "> " } else { "  " });
         io::stdout().flush().unwrap();
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+        if io::stdin().read_line(&mut input).unwrap() == 0 {
+            break;
+        }
         let result = lisp.eval_line(input.as_str());
         match result {
-            Err(LispError::EOF) => break,
-            _ => println!("{:?}", result)
+            // Keep reading until the form is complete
+            Err(ref err) if err.is_incomplete() => continue,
+            Err(LispError::EOF) => (),
+            Err(err) => println!("{}", lisp.render_error(&err)),
+            Ok(node) => println!("{}", node),
         }
+        input.clear();
     }
 }
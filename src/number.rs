@@ -0,0 +1,258 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul, Sub};
+use bigint::BigInt;
+use parser::Node;
+
+// Keeps `expt` from trying to allocate more than a few hundred KB.
+const MAX_EXPT_BITS: u64 = 1 << 20;
+
+/// A numeric value taken out of a `Node`. Integer operations that overflow
+/// `i64` carry on with a `BigInt`, and operations between an integer and a
+/// float promote the integer to a float.
+///
+/// `Big` only ever holds values outside the range of `i64`, so that every
+/// integer has a single representation.
+#[derive(Debug, Clone)]
+pub enum Number {
+    Integer(i64),
+    Big(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    pub fn from_node(node: &Node) -> Option<Number> {
+        match *node {
+            Node::Integer(i) => Some(Number::Integer(i)),
+            Node::BigInt(ref b) => Some(Number::Big(b.clone())),
+            Node::Float(f) => Some(Number::Float(f)),
+            _ => None,
+        }
+    }
+
+    pub fn from_big(b: BigInt) -> Number {
+        match b.to_i64() {
+            Some(i) => Number::Integer(i),
+            None => Number::Big(b),
+        }
+    }
+
+    pub fn into_node(self) -> Node {
+        match self {
+            Number::Integer(i) => Node::Integer(i),
+            Number::Big(b) => Node::BigInt(b),
+            Number::Float(f) => Node::Float(f),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match *self {
+            Number::Integer(i) => i as f64,
+            Number::Big(ref b) => b.to_f64(),
+            Number::Float(f) => f,
+        }
+    }
+
+    fn to_big(&self) -> Option<BigInt> {
+        match *self {
+            Number::Integer(i) => Some(BigInt::from_i64(i)),
+            Number::Big(ref b) => Some(b.clone()),
+            Number::Float(_) => None,
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        match *self {
+            Number::Integer(_) | Number::Big(_) => true,
+            Number::Float(_) => false,
+        }
+    }
+
+    /// Rounds a float with `f`. Integers are already whole, so they're
+    /// returned as they are.
+    pub fn round_with<F>(self, f: F) -> Number where F: Fn(f64) -> f64 {
+        match self {
+            Number::Float(x) => Number::Float(f(x)),
+            n => n,
+        }
+    }
+
+    pub fn inexact_with<F>(self, f: F) -> Number where F: Fn(f64) -> f64 {
+        Number::Float(f(self.to_f64()))
+    }
+
+    pub fn to_inexact(self) -> Number {
+        Number::Float(self.to_f64())
+    }
+
+    /// Returns `None` if the number has no exact integer representation.
+    pub fn to_exact(&self) -> Option<Number> {
+        match *self {
+            Number::Float(x) => BigInt::from_f64(x).map(Number::from_big),
+            ref n => Some(n.clone()),
+        }
+    }
+
+    /// The square root stays exact for perfect squares.
+    pub fn sqrt(self) -> Number {
+        if let Number::Integer(i) = self {
+            if i >= 0 {
+                let r = (i as f64).sqrt() as i64;
+                if let Some(r) = (r - 1..r + 2).find(|r| r.checked_mul(*r) == Some(i)) {
+                    return Number::Integer(r)
+                }
+            }
+        }
+        self.inexact_with(f64::sqrt)
+    }
+
+    /// Returns `None` if the exact result would be unreasonably large.
+    pub fn checked_expt(self, other: Number) -> Option<Number> {
+        if let (Some(a), Number::Integer(b)) = (self.to_big(), &other) {
+            if let Ok(b) = u64::try_from(*b) {
+                if a.bits() > 1 && a.bits().saturating_mul(b) > MAX_EXPT_BITS {
+                    return None
+                }
+                return Some(Number::from_big(a.pow(b)))
+            }
+        }
+        Some(Number::Float(self.to_f64().powf(other.to_f64())))
+    }
+
+    /// Returns `None` when dividing an exact number by exact zero. Float
+    /// division follows IEEE 754 instead.
+    pub fn checked_div(self, other: Number) -> Option<Number> {
+        if let (true, Number::Integer(0)) = (self.is_exact(), &other) {
+            return None
+        }
+        Some(self.promote(other, i64::checked_div, |a, b| a / b, |a, b| a / b))
+    }
+
+    /// Applies `f` to `i64`s, `g` to integers when `f` overflows or either
+    /// side is already big, and `h` to anything involving a float.
+    fn promote<F, G, H>(self, other: Number, f: F, g: G, h: H) -> Number
+        where F: Fn(i64, i64) -> Option<i64>, G: Fn(&BigInt, &BigInt) -> BigInt, H: Fn(f64, f64) -> f64 {
+
+        if let (Number::Integer(a), Number::Integer(b)) = (&self, &other) {
+            if let Some(i) = f(*a, *b) {
+                return Number::Integer(i)
+            }
+        }
+        match (self.to_big(), other.to_big()) {
+            (Some(a), Some(b)) => Number::from_big(g(&a, &b)),
+            _ => Number::Float(h(self.to_f64(), other.to_f64())),
+        }
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, other: Number) -> Number {
+        self.promote(other, i64::checked_add, |a, b| a + b, |a, b| a + b)
+    }
+}
+
+impl Sub for Number {
+    type Output = Number;
+
+    fn sub(self, other: Number) -> Number {
+        self.promote(other, i64::checked_sub, |a, b| a - b, |a, b| a - b)
+    }
+}
+
+impl Mul for Number {
+    type Output = Number;
+
+    fn mul(self, other: Number) -> Number {
+        self.promote(other, i64::checked_mul, |a, b| a * b, |a, b| a * b)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Integer(i) => write!(f, "{}", i),
+            Number::Big(ref b) => write!(f, "{}", b),
+            Number::Float(x) if x.is_nan() => write!(f, "+nan.0"),
+            Number::Float(x) if x.is_infinite() => write!(f, "{}inf.0", if x > 0.0 { "+" } else { "-" }),
+            // `Debug` keeps the decimal point, so that the output reads back as a float
+            Number::Float(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        if let (Number::Integer(a), Number::Integer(b)) = (self, other) {
+            return a.partial_cmp(b)
+        }
+        match (self.to_big(), other.to_big()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promotion() {
+        assert_eq!(Node::Integer(7), (Number::Integer(3) + Number::Integer(4)).into_node());
+        assert_eq!(Node::Float(3.5), (Number::Integer(3) + Number::Float(0.5)).into_node());
+        assert_eq!(Node::Integer(3), Number::Integer(7).checked_div(Number::Integer(2)).unwrap().into_node());
+        assert_eq!(Node::Float(3.5), Number::Integer(7).checked_div(Number::Float(2.0)).unwrap().into_node());
+        assert!(Number::Integer(1) == Number::Float(1.0));
+        assert!(Number::Integer(1) < Number::Float(1.5));
+        assert!(!(Number::Float(f64::NAN) == Number::Float(f64::NAN)));
+    }
+
+    #[test]
+    fn overflow() {
+        let max = Number::Integer(i64::MAX);
+        let big = max.clone() + Number::Integer(1);
+        assert_eq!(Node::BigInt("9223372036854775808".parse().unwrap()), big.clone().into_node());
+        assert_eq!(Node::Integer(i64::MAX), (big.clone() - Number::Integer(1)).into_node());
+        assert_eq!(
+            Node::BigInt("85070591730234615847396907784232501249".parse().unwrap()),
+            (max.clone() * max.clone()).into_node());
+        assert_eq!(
+            Node::BigInt("9223372036854775808".parse().unwrap()),
+            Number::Integer(i64::MIN).checked_div(Number::Integer(-1)).unwrap().into_node());
+        assert_eq!(Node::Integer(2), (max.clone() * Number::Integer(2)).checked_div(max.clone()).unwrap().into_node());
+        assert!(big > max);
+        assert!(Number::Integer(1).checked_div(Number::Integer(0)).is_none());
+        assert!(Number::Integer(2).checked_expt(Number::Integer(1 << 20)).is_none());
+        assert!(Number::Integer(-1).checked_expt(Number::Integer(i64::MAX)).is_some());
+        assert!(big == Number::Float(9223372036854775808.0));
+        assert_eq!(Node::Integer(i64::MIN), (Number::Integer(0) - big.clone()).into_node());
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(Node::Integer(4), Number::Integer(16).sqrt().into_node());
+        assert_eq!(Node::Float(2f64.sqrt()), Number::Integer(2).sqrt().into_node());
+        assert_eq!(Node::Integer(1024), Number::Integer(2).checked_expt(Number::Integer(10)).unwrap().into_node());
+        assert_eq!(Node::Float(0.5), Number::Integer(2).checked_expt(Number::Integer(-1)).unwrap().into_node());
+        assert_eq!(Node::Float(2.0), Number::Float(2.5).round_with(f64::round_ties_even).into_node());
+        assert_eq!(Node::Integer(3), Number::Float(3.0).to_exact().unwrap().into_node());
+        assert!(Number::Float(3.5).to_exact().is_none());
+        assert_eq!(
+            Node::BigInt("9300000000000000000".parse().unwrap()),
+            Number::Float(9.3e18).to_exact().unwrap().into_node());
+        assert_eq!(
+            Node::BigInt("1267650600228229401496703205376".parse().unwrap()),
+            Number::Integer(2).checked_expt(Number::Integer(100)).unwrap().into_node());
+        assert_eq!("3.0", Number::Float(3.0).to_string());
+        assert_eq!("-42", Number::Integer(-42).to_string());
+    }
+}
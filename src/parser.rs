@@ -1,73 +1,463 @@
+use std::fmt;
+use std::mem;
 use std::rc::Rc;
+use bigint::BigInt;
+use number::Number;
 use lexer::*;
+use eval::Env;
+use span::Span;
+use symbol::Symbol;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Clone)]
 pub enum Node {
     Integer(i64),
-    Keyword(String),
-    List(Vec<Rc<Node>>),
-    QuotedList(Vec<Rc<Node>>),
-    Func(Vec<String>, Vec<Rc<Node>>),
+    BigInt(BigInt),
+    Float(f64),
+    Str(String),
+    Symbol(Symbol),
+    /// `:name`, which evaluates to itself.
+    Keyword(Symbol),
+    /// A cons cell. A chain of them that ends in `Nil` is a proper list,
+    /// which is what code is read as too.
+    Pair(Rc<Node>, Rc<Node>),
+    /// The empty list, which is also what `nil` reads as.
+    Nil,
+    Func(Rc<Function>),
     True,
     False,
 }
 
+/// A function made by `lambda`, `defun` or `define`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Function {
+    /// What it was defined as, for messages. `None` for a `lambda`.
+    pub name: Option<Symbol>,
+    pub params: Vec<Symbol>,
+    pub doc: Option<String>,
+    pub body: Vec<Rc<Node>>,
+    /// The environment the function was created in.
+    pub env: Env,
+}
+
+impl Node {
+    pub fn list(items: Vec<Rc<Node>>) -> Rc<Node> {
+        Node::dotted_list(items, Rc::new(Node::Nil))
+    }
+
+    /// Chains `items` into pairs with `tail` as the cdr of the last one.
+    pub fn dotted_list(items: Vec<Rc<Node>>, tail: Rc<Node>) -> Rc<Node> {
+        items.into_iter().rev().fold(tail, |tl, x| Rc::new(Node::Pair(x, tl)))
+    }
+
+    /// The elements of a proper list, or `None` if this isn't one.
+    pub fn to_vec(&self) -> Option<Vec<Rc<Node>>> {
+        let mut items = Vec::new();
+        let mut node = self;
+        loop {
+            match *node {
+                Node::Pair(ref hd, ref tl) => {
+                    items.push(hd.clone());
+                    node = tl;
+                },
+                Node::Nil => return Some(items),
+                _ => return None,
+            }
+        }
+    }
+}
+
+// Dropping a long or deeply nested list would otherwise recurse once per
+// pair. The car and cdr that nobody else holds are moved out onto a list of
+// pairs still to be taken apart, so that only one is dropped at a time.
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut pairs = Vec::new();
+        take_pairs(self, &mut pairs);
+        while let Some(mut node) = pairs.pop() {
+            take_pairs(&mut node, &mut pairs);
+        }
+    }
+}
+
+fn take_pairs(node: &mut Node, pairs: &mut Vec<Node>) {
+    if let Node::Pair(ref mut hd, ref mut tl) = *node {
+        for x in [hd, tl] {
+            if let Some(x) = Rc::get_mut(x) {
+                if let Node::Pair(..) = *x {
+                    pairs.push(mem::replace(x, Node::Nil))
+                }
+            }
+        }
+    }
+}
+
+// Like the derived impls, but without recursing on pairs, for the same
+// reason as `Drop`.
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        // The cdrs still to compare once the car is done
+        let mut rest = Vec::new();
+        let (mut a, mut b) = (self, other);
+        loop {
+            if let (Node::Pair(x, xs), Node::Pair(y, ys)) = (a, b) {
+                rest.push((&**xs, &**ys));
+                a = x;
+                b = y;
+                continue
+            }
+            if !leaf_eq(a, b) {
+                return false
+            }
+            match rest.pop() {
+                Some((x, y)) => {
+                    a = x;
+                    b = y;
+                },
+                None => return true,
+            }
+        }
+    }
+}
+
+// Compares nodes other than pairs, and pairs with anything else.
+fn leaf_eq(a: &Node, b: &Node) -> bool {
+    match (a, b) {
+        (Node::Integer(x), Node::Integer(y)) => x == y,
+        (Node::BigInt(x), Node::BigInt(y)) => x == y,
+        (Node::Float(x), Node::Float(y)) => x == y,
+        (Node::Str(x), Node::Str(y)) => x == y,
+        (Node::Symbol(x), Node::Symbol(y)) => x == y,
+        (Node::Keyword(x), Node::Keyword(y)) => x == y,
+        (Node::Func(x), Node::Func(y)) => x == y,
+        (Node::Nil, Node::Nil) | (Node::True, Node::True) | (Node::False, Node::False) => true,
+        _ => false,
+    }
+}
+
+// What's left to print of a node, for printing without recursion.
+enum Item<'a> {
+    Node(&'a Node),
+    /// The rest of a list after an element, up to the closing `)`.
+    Tail(&'a Node),
+    Text(&'static str),
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut items = vec![Item::Node(self)];
+        while let Some(item) = items.pop() {
+            let node = match item {
+                Item::Node(node) => node,
+                Item::Text(s) => {
+                    write!(f, "{}", s)?;
+                    continue
+                },
+                Item::Tail(_) => unreachable!(),
+            };
+            match *node {
+                Node::Integer(i) => f.debug_tuple("Integer").field(&i).finish()?,
+                Node::BigInt(ref b) => f.debug_tuple("BigInt").field(b).finish()?,
+                Node::Float(x) => f.debug_tuple("Float").field(&x).finish()?,
+                Node::Str(ref s) => f.debug_tuple("Str").field(s).finish()?,
+                Node::Symbol(name) => f.debug_tuple("Symbol").field(&name).finish()?,
+                Node::Keyword(name) => f.debug_tuple("Keyword").field(&name).finish()?,
+                Node::Func(ref func) => f.debug_tuple("Func").field(func).finish()?,
+                Node::Pair(ref hd, ref tl) => {
+                    write!(f, "Pair(")?;
+                    items.extend([Item::Text(")"), Item::Node(tl), Item::Text(", "), Item::Node(hd)]);
+                },
+                Node::Nil => write!(f, "Nil")?,
+                Node::True => write!(f, "True")?,
+                Node::False => write!(f, "False")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Node {
+    /// Prints strings without quotes and escapes, unlike `Display`, which is
+    /// meant to be read back.
+    pub fn display(&self) -> impl fmt::Display + '_ {
+        Printer { node: self, write: false }
+    }
+}
+
+/// What `Display` prints reads back as an equal node, except for functions,
+/// which look like `#<lambda (n)>`, or `#<lambda fib (n)>` if they have a
+/// name.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Printer { node: self, write: true }.fmt(f)
+    }
+}
+
+// Prints `node` as an s-expression. Strings are quoted and escaped only when
+// `write` is set, like the `write` and `display` procedures of Scheme.
+struct Printer<'a> {
+    node: &'a Node,
+    write: bool,
+}
+
+impl<'a> fmt::Display for Printer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut items = vec![Item::Node(self.node)];
+        while let Some(item) = items.pop() {
+            let node = match item {
+                Item::Node(node) => node,
+                Item::Tail(node) => {
+                    match *node {
+                        Node::Pair(ref hd, ref tl) => {
+                            write!(f, " ")?;
+                            items.extend([Item::Tail(tl), Item::Node(hd)]);
+                        },
+                        Node::Nil => write!(f, ")")?,
+                        ref x => {
+                            write!(f, " . ")?;
+                            items.extend([Item::Text(")"), Item::Node(x)]);
+                        },
+                    }
+                    continue
+                },
+                Item::Text(s) => {
+                    write!(f, "{}", s)?;
+                    continue
+                },
+            };
+            match *node {
+                Node::Integer(i) => write!(f, "{}", i)?,
+                Node::BigInt(ref b) => write!(f, "{}", b)?,
+                Node::Float(x) => write!(f, "{}", Number::Float(x))?,
+                Node::Str(ref s) if self.write => write_str(f, s)?,
+                Node::Str(ref s) => write!(f, "{}", s)?,
+                Node::Symbol(name) => write!(f, "{}", name)?,
+                Node::Keyword(name) => write!(f, ":{}", name)?,
+                Node::Pair(ref hd, ref tl) => {
+                    write!(f, "(")?;
+                    items.extend([Item::Tail(tl), Item::Node(hd)]);
+                },
+                Node::Nil => write!(f, "()")?,
+                Node::Func(ref func) => {
+                    let params: Vec<_> = func.params.iter().map(|x| x.name()).collect();
+                    match func.name {
+                        Some(name) => write!(f, "#<lambda {} ({})>", name, params.join(" "))?,
+                        None => write!(f, "#<lambda ({})>", params.join(" "))?,
+                    }
+                },
+                Node::True => write!(f, "#t")?,
+                Node::False => write!(f, "#f")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Quotes `s` with only the escapes `Lexer` understands.
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    /// A `)` without a matching `(`.
+    UnexpectedCloseParen,
+    /// The input ended inside a list, or there was no form at all.
+    UnexpectedEOF,
+    /// A `'` that isn't followed by a datum.
+    DanglingQuote,
+    /// A `.` that isn't between the elements of a list and its last cdr,
+    /// like in `(. a)` or `(a . b c)`.
+    MisplacedDot,
+    /// Lists and quotes nested deeper than `MAX_DEPTH`.
+    TooDeep,
+}
+
+/// `span` points at the offending token: the `)`, the `'`, the `.`, the `(`
+/// of the list that isn't closed, or the first token that's too deep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self.kind {
+            ParseErrorKind::UnexpectedCloseParen => "Unexpected `)`",
+            ParseErrorKind::UnexpectedEOF => "Unexpected end of input",
+            ParseErrorKind::DanglingQuote => "`'` should be followed by a datum",
+            ParseErrorKind::MisplacedDot => "`.` should be followed by exactly one datum before `)`",
+            ParseErrorKind::TooDeep => return write!(f, "Lists are nested more than {} deep", MAX_DEPTH),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// How deep lists and quotes can be nested. Each level takes a few frames
+/// of the Rust stack, so going much deeper would overflow it and abort the
+/// process.
+pub const MAX_DEPTH: usize = 500;
+
 #[derive(Debug)]
 pub struct Parser {
-    tokens: Vec<ExtendedToken>
+    tokens: Vec<ExtendedToken>,
+    pos: usize,
+    file: Option<Rc<String>>,
+    spans: Vec<(Rc<Node>, Span)>,
+    depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<ExtendedToken>) -> Self {
-        Parser { tokens: tokens }
+        Parser::with_file(tokens, None)
     }
 
-    fn next_token(&mut self) -> Option<ExtendedToken> {
-        if self.tokens.is_empty() {
-            None
-        }
-        else {
-            Some(self.tokens.remove(0))
+    /// `file` ends up in the spans of nodes and errors, for telling sources
+    /// apart.
+    pub fn with_file(tokens: Vec<ExtendedToken>, file: Option<Rc<String>>) -> Self {
+        Parser { tokens, pos: 0, file, spans: Vec::new(), depth: 0 }
+    }
+
+    /// Hands over the span of every node parsed so far, for a `SourceMap`.
+    pub fn take_spans(&mut self) -> Vec<(Rc<Node>, Span)> {
+        ::std::mem::take(&mut self.spans)
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn next_token(&mut self) -> Option<&ExtendedToken> {
+        let token = self.tokens.get(self.pos);
+        if token.is_some() {
+            self.pos += 1;
         }
+        token
     }
 
-    pub fn parse(&mut self) -> Option<Rc<Node>> {
-        match self.next_token() {
-            // Check EOF
-            None => None,
-            Some(token) => match token.token {
-                Token::LParen => Some(Rc::new(Node::List(self.parse_list()))),
-                Token::RParen => None,
-                Token::Integer(i) => Some(Rc::new(Node::Integer(i))),
-                Token::Keyword(s) => Some(Rc::new(Node::Keyword(s))),
-                Token::Quote => self.parse_quoted_list(),
-            }
+    fn peek_token(&self) -> Option<&ExtendedToken> {
+        self.tokens.get(self.pos)
+    }
+
+    // `len` chars from the start of `token`.
+    fn span(&self, token: &ExtendedToken, len: usize) -> Span {
+        Span::new(self.file.clone(), token.line, token.column, token.index, len)
+    }
+
+    fn error(&self, kind: ParseErrorKind, token: &ExtendedToken) -> ParseError {
+        ParseError { kind, span: self.span(token, token.len) }
+    }
+
+    // Where the input ends, for errors that have no token to point at. The
+    // last token is taken to be on a single line.
+    fn eof_error(&self) -> ParseError {
+        let span = match self.tokens.last() {
+            Some(x) => Span::new(self.file.clone(), x.line, x.column + x.len, x.index + x.len, 0),
+            None => Span::new(self.file.clone(), 1, 1, 0, 0),
+        };
+        ParseError { kind: ParseErrorKind::UnexpectedEOF, span }
+    }
+
+    // Wraps `node` and records that it spans from `start` to the end of the
+    // last token consumed.
+    fn spanned(&mut self, node: Node, start: &ExtendedToken) -> Rc<Node> {
+        let end = self.tokens[self.pos - 1].index + self.tokens[self.pos - 1].len;
+        let node = Rc::new(node);
+        let span = self.span(start, end - start.index);
+        self.spans.push((node.clone(), span));
+        node
+    }
+
+    pub fn parse(&mut self) -> Result<Rc<Node>, ParseError> {
+        let token = match self.next_token() {
+            Some(token) => token.clone(),
+            None => return Err(self.eof_error()),
+        };
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(ParseErrorKind::TooDeep, &token))
         }
+        self.depth += 1;
+        let node = self.parse_token(&token);
+        self.depth -= 1;
+        Ok(self.spanned(node?, &token))
+    }
+
+    // Parses the datum that starts with `token`, which has been consumed.
+    fn parse_token(&mut self, token: &ExtendedToken) -> Result<Node, ParseError> {
+        Ok(match token.token {
+            Token::LParen => self.parse_list(token)?,
+            Token::RParen => return Err(self.error(ParseErrorKind::UnexpectedCloseParen, token)),
+            Token::Integer(i) => Node::Integer(i),
+            Token::BigInt(ref b) => Node::BigInt(b.clone()),
+            Token::Float(f) => Node::Float(f),
+            Token::Str(ref s) => Node::Str(s.clone()),
+            Token::Symbol(ref s) => Node::Symbol(Symbol::intern(s)),
+            Token::Keyword(ref s) => Node::Keyword(Symbol::intern(s)),
+            Token::True => Node::True,
+            Token::False => Node::False,
+            Token::Nil => Node::Nil,
+            Token::Quote => self.parse_quoted(token)?,
+        })
     }
 
-    fn parse_list(&mut self) -> Vec<Rc<Node>> {
+    // Parses the elements after `open` up to the matching `)`.
+    fn parse_list(&mut self, open: &ExtendedToken) -> Result<Node, ParseError> {
         let mut list = Vec::new();
-        while let Some(node) = self.parse() {
-            list.push(node)
-        }
-        list
-    }
-
-    fn parse_quoted_list(&mut self) -> Option<Rc<Node>> {
-        match self.next_token() {
-            // Check EOF
-            None => None,
-            Some(token) => match token.token {
-                Token::LParen => {
-                    let mut list = Vec::new();
-                    while let Some(node) = self.parse() {
-                        list.push(node)
+        let tail = loop {
+            match self.peek_token().map(|x| &x.token) {
+                Some(&Token::RParen) => {
+                    self.next_token();
+                    break Rc::new(Node::Nil)
+                },
+                Some(Token::Symbol(s)) if s == "." => {
+                    let dot = self.tokens[self.pos].clone();
+                    self.next_token();
+                    if list.is_empty() {
+                        return Err(self.error(ParseErrorKind::MisplacedDot, &dot))
+                    }
+                    let tail = match self.peek_token().map(|x| &x.token) {
+                        None => return Err(self.error(ParseErrorKind::UnexpectedEOF, open)),
+                        Some(&Token::RParen) => return Err(self.error(ParseErrorKind::MisplacedDot, &dot)),
+                        Some(_) => self.parse()?,
+                    };
+                    match self.next_token().map(|x| &x.token) {
+                        Some(&Token::RParen) => break tail,
+                        Some(_) => return Err(self.error(ParseErrorKind::MisplacedDot, &dot)),
+                        None => return Err(self.error(ParseErrorKind::UnexpectedEOF, open)),
                     }
-                    Some(Rc::new(Node::QuotedList(list)))
                 },
-                // TODO
-                _ => None
+                Some(_) => list.push(self.parse()?),
+                None => return Err(self.error(ParseErrorKind::UnexpectedEOF, open)),
             }
+        };
+        if list.is_empty() {
+            return Ok(Node::Nil)
+        }
+        let head = list.remove(0);
+        Ok(Node::Pair(head, Node::dotted_list(list, tail)))
+    }
+
+    // `'x` is read as `(quote x)`, where `quote` spans the `'`.
+    fn parse_quoted(&mut self, quote: &ExtendedToken) -> Result<Node, ParseError> {
+        match self.peek_token().map(|x| &x.token) {
+            None | Some(&Token::RParen) => Err(self.error(ParseErrorKind::DanglingQuote, quote)),
+            Some(_) => {
+                let keyword = Rc::new(Node::Symbol(Symbol::intern("quote")));
+                let span = self.span(quote, quote.len);
+                self.spans.push((keyword.clone(), span));
+                Ok(Node::Pair(keyword, Node::list(vec![self.parse()?])))
+            },
         }
     }
 }
@@ -80,44 +470,44 @@ mod tests {
     fn parse0() {
         let tokens = vec![
             ExtendedToken {
-                token: Token::LParen, index: 0, len: 1
+                token: Token::LParen, index: 0, len: 1, byte: 0, line: 1, column: 1
             },
             ExtendedToken {
-                token: Token::Keyword(String::from("+")), index: 1, len: 1
+                token: Token::Symbol(String::from("+")), index: 1, len: 1, byte: 1, line: 1, column: 2
             },
             ExtendedToken {
-                token: Token::Integer(1), index: 3, len: 1
+                token: Token::Integer(1), index: 3, len: 1, byte: 3, line: 1, column: 4
             },
             ExtendedToken {
-                token: Token::LParen, index: 5, len: 1
+                token: Token::LParen, index: 5, len: 1, byte: 5, line: 1, column: 6
             },
             ExtendedToken {
-                token: Token::Keyword(String::from("-")), index: 6, len: 1
+                token: Token::Symbol(String::from("-")), index: 6, len: 1, byte: 6, line: 1, column: 7
             },
             ExtendedToken {
-                token: Token::Integer(5), index: 8, len: 1
+                token: Token::Integer(5), index: 8, len: 1, byte: 8, line: 1, column: 9
             },
             ExtendedToken {
-                token: Token::Integer(2), index: 10, len: 1
+                token: Token::Integer(2), index: 10, len: 1, byte: 10, line: 1, column: 11
             },
             ExtendedToken {
-                token: Token::RParen, index: 11, len: 1
+                token: Token::RParen, index: 11, len: 1, byte: 11, line: 1, column: 12
             },
             ExtendedToken {
-                token: Token::RParen, index: 12, len: 1
+                token: Token::RParen, index: 12, len: 1, byte: 12, line: 1, column: 13
             },
         ];
         assert_eq!(
-            Node::List(
+            *Node::list(
                 vec![
-                    Rc::new(Node::Keyword(String::from("+"))),
+                    Rc::new(Node::Symbol(Symbol::intern("+"))),
                     Rc::new(Node::Integer(1)),
-                    Rc::new(Node::List(
+                    Node::list(
                         vec![
-                            Rc::new(Node::Keyword(String::from("-"))),
+                            Rc::new(Node::Symbol(Symbol::intern("-"))),
                             Rc::new(Node::Integer(5)),
                             Rc::new(Node::Integer(2)),
-                        ]))]),
+                        ])]),
             *Parser::new(tokens).parse().unwrap()
         );
     }
@@ -126,22 +516,122 @@ mod tests {
     fn parse1() {
         let tokens = vec![
             ExtendedToken {
-                token: Token::Quote, index: 0, len: 1
+                token: Token::Quote, index: 0, len: 1, byte: 0, line: 1, column: 1
             },
             ExtendedToken {
-                token: Token::LParen, index: 1, len: 1
+                token: Token::LParen, index: 1, len: 1, byte: 1, line: 1, column: 2
             },
             ExtendedToken {
-                token: Token::Integer(1), index: 2, len: 1
+                token: Token::Integer(1), index: 2, len: 1, byte: 2, line: 1, column: 3
             },
             ExtendedToken {
-                token: Token::RParen, index: 3, len: 1
+                token: Token::RParen, index: 3, len: 1, byte: 3, line: 1, column: 4
             },
         ];
         assert_eq!(
-            Node::QuotedList(vec![Rc::new(Node::Integer(1))]),
+            *Node::list(vec![
+                Rc::new(Node::Symbol(Symbol::intern("quote"))),
+                Node::list(vec![Rc::new(Node::Integer(1))]),
+            ]),
             *Parser::new(tokens).parse().unwrap()
         );
     }
-}
 
+    #[test]
+    fn parse_dotted() {
+        let parse = |s| Parser::new(Lexer::new(s).tokenize().unwrap()).parse().unwrap();
+        let (a, b, c) = (Symbol::intern("a"), Symbol::intern("b"), Symbol::intern("c"));
+
+        assert_eq!(
+            Node::Pair(Rc::new(Node::Symbol(a)), Rc::new(Node::Symbol(b))),
+            *parse("(a . b)"));
+        assert_eq!(
+            Node::Pair(
+                Rc::new(Node::Symbol(a)),
+                Rc::new(Node::Pair(Rc::new(Node::Symbol(b)), Rc::new(Node::Symbol(c))))),
+            *parse("(a b . c)"));
+        assert_eq!(
+            *Node::list(vec![Rc::new(Node::Symbol(Symbol::intern(".a"))), Rc::new(Node::Symbol(Symbol::intern("...")))]),
+            *parse("(.a ...)"));
+        assert_eq!(Node::Nil, *parse("()"));
+        assert_eq!("(a b . c)", parse("(a b . c)").to_string());
+        assert_eq!("(a b c)", parse("(a . (b . (c . ())))").to_string());
+        assert_eq!("Pair(Symbol(\"a\"), Pair(Pair(Integer(1), Nil), Str(\"b\")))", format!("{:?}", parse("(a (1) . \"b\")")));
+        assert_eq!("(1 (2 . 3))", Node::list(vec![
+            Rc::new(Node::Integer(1)),
+            Rc::new(Node::Pair(Rc::new(Node::Integer(2)), Rc::new(Node::Integer(3)))),
+        ]).to_string());
+    }
+
+    #[test]
+    fn parse_error() {
+        let parse = |s| Parser::new(Lexer::new(s).tokenize().unwrap()).parse();
+
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnexpectedCloseParen, span: Span::new(None, 1, 3, 2, 1) }),
+            parse("  )"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnexpectedEOF, span: Span::new(None, 1, 4, 3, 1) }),
+            parse("(+ (- 1 2"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnexpectedEOF, span: Span::new(None, 1, 1, 0, 1) }),
+            parse("(+ 1 2"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnexpectedEOF, span: Span::new(None, 1, 1, 0, 0) }),
+            parse(""));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::DanglingQuote, span: Span::new(None, 1, 4, 3, 1) }),
+            parse("(a '"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::DanglingQuote, span: Span::new(None, 1, 4, 3, 1) }),
+            parse("(a ')"));
+
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::MisplacedDot, span: Span::new(None, 1, 2, 1, 1) }),
+            parse("(. a)"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::MisplacedDot, span: Span::new(None, 1, 4, 3, 1) }),
+            parse("(a . b c)"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::MisplacedDot, span: Span::new(None, 1, 4, 3, 1) }),
+            parse("(a .)"));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnexpectedEOF, span: Span::new(None, 1, 1, 0, 1) }),
+            parse("(a . b"));
+
+        // Too deep to parse without overflowing the stack
+        let deep = "(".repeat(100000);
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::TooDeep, span: Span::new(None, 1, MAX_DEPTH + 1, MAX_DEPTH, 1) }),
+            parse(&deep));
+        let deep = format!("{}1", "'".repeat(100000));
+        assert_eq!(ParseErrorKind::TooDeep, parse(&deep).unwrap_err().kind);
+        let deep = format!("{}{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert!(parse(&deep).is_ok());
+
+        let mut parser = Parser::new(Lexer::new("1 (2) )").tokenize().unwrap());
+        assert_eq!(Node::Integer(1), *parser.parse().unwrap());
+        assert_eq!(*Node::list(vec![Rc::new(Node::Integer(2))]), *parser.parse().unwrap());
+        assert!(!parser.is_eof());
+        assert_eq!(ParseErrorKind::UnexpectedCloseParen, parser.parse().unwrap_err().kind);
+        assert!(parser.is_eof());
+    }
+
+    #[test]
+    fn spans() {
+        let mut parser = Parser::new(Lexer::new("(f 1\n  'x)").tokenize().unwrap());
+        let node = parser.parse().unwrap();
+        let spans = parser.take_spans();
+        let span = |x: &Rc<Node>| spans.iter().find(|y| Rc::ptr_eq(x, &y.0)).map(|y| y.1.clone());
+
+        assert_eq!(Some(Span::new(None, 1, 1, 0, 10)), span(&node));
+        let xs = node.to_vec().unwrap();
+        assert_eq!(Some(Span::new(None, 1, 2, 1, 1)), span(&xs[0]));
+        assert_eq!(Some(Span::new(None, 1, 4, 3, 1)), span(&xs[1]));
+        assert_eq!(Some(Span::new(None, 2, 3, 7, 2)), span(&xs[2]));
+        let ys = xs[2].to_vec().unwrap();
+        assert_eq!(Some(Span::new(None, 2, 3, 7, 1)), span(&ys[0]));
+        assert_eq!(Some(Span::new(None, 2, 4, 8, 1)), span(&ys[1]));
+        assert!(parser.take_spans().is_empty());
+    }
+}
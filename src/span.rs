@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use parser::Node;
+
+/// A range of characters in a source. `line` and `column` are where it
+/// starts, and the rest is counted in chars like `ExtendedToken` does.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: Option<Rc<String>>,
+    pub line: usize,
+    pub column: usize,
+    pub index: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(file: Option<Rc<String>>, line: usize, column: usize, index: usize, len: usize) -> Self {
+        Span { file, line, column, index, len }
+    }
+
+    /// Shows the line the span starts on with the span underlined, like
+    ///
+    /// ```text
+    ///  --> lib.lisp:2:4
+    ///   |
+    /// 2 | (+ x1 1)
+    ///   |    ^^
+    /// ```
+    ///
+    /// A span that runs past the end of the line is underlined up to it.
+    pub fn render(&self, source: &str) -> String {
+        let (line, column) = (self.line, self.column);
+        let text = source.lines().nth(line - 1).unwrap_or("");
+        let width = self.len.min(text.chars().count().saturating_sub(column - 1)).max(1);
+        let number = line.to_string();
+        let margin = " ".repeat(number.len());
+        format!("{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+                margin, self.file.as_ref().map_or("<input>", |x| x.as_str()), line, column,
+                margin,
+                number, text,
+                margin, " ".repeat(column - 1), "^".repeat(width))
+    }
+}
+
+/// Remembers which span each parsed node came from, along with the sources
+/// themselves so that errors can be rendered later.
+///
+/// Nodes are looked up by the address of their `Rc`. Holding a `Weak` keeps
+/// the allocation, and so the address, from being reused by another node.
+///
+/// Sources are looked up by the identity of the `Rc` naming them, so two
+/// inputs with the same name (like two lines typed into the REPL) don't get
+/// mixed up. A source is forgotten once no span refers to it.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    spans: HashMap<*const Node, (Weak<Node>, Span)>,
+    sources: Vec<Source>,
+}
+
+#[derive(Debug)]
+struct Source {
+    file: Rc<String>,
+    text: Rc<String>,
+    // Whether `file` is the path it was read from, rather than a name like
+    // `<input>`
+    is_file: bool,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    pub fn add_source(&mut self, file: Rc<String>, source: &str) {
+        self.push(file, source, false)
+    }
+
+    /// Like `add_source`, for a source read from the path `file`.
+    pub fn add_file(&mut self, file: Rc<String>, source: &str) {
+        self.push(file, source, true)
+    }
+
+    fn push(&mut self, file: Rc<String>, source: &str, is_file: bool) {
+        self.sources.retain(|x| Rc::strong_count(&x.file) > 1);
+        self.sources.push(Source { file, text: Rc::new(source.to_string()), is_file });
+    }
+
+    pub fn source(&self, file: &Rc<String>) -> Option<Rc<String>> {
+        self.sources.iter().find(|x| Rc::ptr_eq(&x.file, file)).map(|x| x.text.clone())
+    }
+
+    /// Whether `file` was added with `add_file`.
+    pub fn is_file(&self, file: &Rc<String>) -> bool {
+        self.sources.iter().any(|x| Rc::ptr_eq(&x.file, file) && x.is_file)
+    }
+
+    pub fn add_spans(&mut self, spans: Vec<(Rc<Node>, Span)>) {
+        // Forget nodes that have been dropped since the last time
+        self.spans.retain(|_, v| v.0.strong_count() > 0);
+        for (node, span) in spans {
+            self.spans.insert(&*node as *const Node, (Rc::downgrade(&node), span));
+        }
+    }
+
+    pub fn get(&self, node: &Rc<Node>) -> Option<Span> {
+        self.spans.get(&(&**node as *const Node)).map(|v| v.1.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let source = "(setq x 1)\n(+ x1 1)\n";
+        assert_eq!(
+            " --> <input>:2:4\n  |\n2 | (+ x1 1)\n  |    ^^",
+            Span::new(None, 2, 4, 14, 2).render(source));
+        assert_eq!(
+            " --> lib.lisp:1:1\n  |\n1 | (setq x 1)\n  | ^^^^^^^^^^",
+            Span::new(Some(Rc::new(String::from("lib.lisp"))), 1, 1, 0, 20).render(source));
+        assert_eq!(
+            " --> <input>:3:1\n  |\n3 | \n  | ^",
+            Span::new(None, 3, 1, 20, 0).render(source));
+    }
+
+    #[test]
+    fn source_map() {
+        let mut map = SourceMap::new();
+        let node = Rc::new(Node::Integer(1));
+        let other = Rc::new(Node::Integer(1));
+        map.add_spans(vec![(node.clone(), Span::new(None, 1, 4, 3, 1))]);
+        assert_eq!(Some(Span::new(None, 1, 4, 3, 1)), map.get(&node));
+        assert_eq!(Some(Span::new(None, 1, 4, 3, 1)), map.get(&node.clone()));
+        assert_eq!(None, map.get(&other));
+
+        drop(node);
+        map.add_spans(Vec::new());
+        assert!(map.spans.is_empty());
+    }
+
+    #[test]
+    fn sources() {
+        let mut map = SourceMap::new();
+        let first = Rc::new(String::from("<input>"));
+        let second = Rc::new(String::from("<input>"));
+        map.add_source(first.clone(), "(f 1)");
+        map.add_source(second.clone(), "(g 2)");
+        assert_eq!("(f 1)", *map.source(&first).unwrap());
+        assert_eq!("(g 2)", *map.source(&second).unwrap());
+        assert!(!map.is_file(&first));
+
+        let file = Rc::new(String::from("lib/a.lisp"));
+        map.add_file(file.clone(), "(h 3)");
+        assert_eq!("(h 3)", *map.source(&file).unwrap());
+        assert!(map.is_file(&file));
+        assert!(!map.is_file(&Rc::new(String::from("lib/a.lisp"))));
+
+        drop(first);
+        map.add_source(Rc::new(String::from("a.lisp")), "");
+        assert_eq!(3, map.sources.len());
+    }
+}
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An interned name. Comparing and hashing one is as cheap as for the `u32`
+/// inside, and `Symbol::intern` gives the same `Symbol` for the same name.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+// Names are never freed, so they can be handed out as `&'static str`.
+#[derive(Default)]
+struct Interner {
+    ids: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.ids.get(name) {
+                return symbol
+            }
+            let symbol = Symbol(interner.names.len() as u32);
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            interner.names.push(name);
+            interner.ids.insert(name, symbol);
+            symbol
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern() {
+        let a = Symbol::intern("list->vector");
+        assert_eq!(a, Symbol::intern("list->vector"));
+        assert_ne!(a, Symbol::intern("list->string"));
+        assert_eq!("list->vector", a.name());
+        assert_eq!("\"list->vector\"", format!("{:?}", a));
+        assert_eq!("list->vector", a.to_string());
+    }
+}
@@ -0,0 +1,10 @@
+/// Which values count as false in conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Truthiness {
+    /// `#f` and `()` are false like in Common Lisp, where `()` is `nil` and
+    /// evaluates to itself. List functions take `#f` as `()` too.
+    #[default]
+    CommonLisp,
+    /// Only `#f` is false like in Scheme, where evaluating `()` is an error.
+    Scheme,
+}